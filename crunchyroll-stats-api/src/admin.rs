@@ -0,0 +1,71 @@
+use anyhow::{anyhow, Result};
+use sha2::{Digest, Sha256};
+
+const MIN_TOKEN_LEN: usize = 32;
+
+/// Token guarding the `/api/admin` routes, from `ADMIN_TOKEN`. Without one
+/// the admin routes are disabled.
+pub struct AdminToken {
+    digest: Option<[u8; 32]>,
+}
+
+impl AdminToken {
+    pub fn new(token: Option<&str>) -> Result<Self> {
+        let digest = match token {
+            Some(token) if token.len() < MIN_TOKEN_LEN => {
+                return Err(anyhow!("ADMIN_TOKEN must be at least {} characters", MIN_TOKEN_LEN));
+            }
+            Some(token) => Some(digest(token)),
+            None => None,
+        };
+        Ok(Self { digest })
+    }
+
+    pub fn from_env() -> Result<Self> {
+        let token = std::env::var("ADMIN_TOKEN").ok().filter(|token| !token.is_empty());
+        Self::new(token.as_deref())
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.digest.is_some()
+    }
+
+    /// Compares digests rather than the tokens themselves, so how long the
+    /// comparison takes says nothing about the token.
+    pub fn matches(&self, candidate: &str) -> bool {
+        self.digest.is_some_and(|digest| digest == self::digest(candidate))
+    }
+}
+
+fn digest(token: &str) -> [u8; 32] {
+    Sha256::digest(token.as_bytes()).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOKEN: &str = "0123456789abcdef0123456789abcdef";
+
+    #[test]
+    fn matches_only_the_configured_token() {
+        let admin = AdminToken::new(Some(TOKEN)).unwrap();
+        assert!(admin.is_enabled());
+        assert!(admin.matches(TOKEN));
+        assert!(!admin.matches("0123456789abcdef0123456789abcdeF"));
+        assert!(!admin.matches(""));
+    }
+
+    #[test]
+    fn disabled_without_a_token() {
+        let admin = AdminToken::new(None).unwrap();
+        assert!(!admin.is_enabled());
+        assert!(!admin.matches(""));
+        assert!(!admin.matches(TOKEN));
+    }
+
+    #[test]
+    fn rejects_short_tokens() {
+        assert!(AdminToken::new(Some("hunter22")).is_err());
+    }
+}
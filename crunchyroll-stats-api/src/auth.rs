@@ -7,8 +7,18 @@ pub struct CrunchyrollClient {
 }
 
 impl CrunchyrollClient {
-    pub async fn new(email: &str, password: &str) -> Result<Self> {
-        let client = Crunchyroll::builder()
+    /// Logs in with the given credentials. `http_client` replaces the
+    /// crunchyroll-rs default client, e.g. to talk to a mock upstream.
+    pub async fn new(
+        email: &str,
+        password: &str,
+        http_client: Option<reqwest::Client>,
+    ) -> Result<Self> {
+        let mut builder = Crunchyroll::builder();
+        if let Some(http_client) = http_client {
+            builder = builder.client(http_client);
+        }
+        let client = builder
             .login_with_credentials(email, password, DeviceIdentifier::default())
             .await?;
         Ok(Self { client })
@@ -0,0 +1,251 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+
+const SCAN_BATCH: usize = 2; // keys per SCAN reply, whatever COUNT asks for
+const MAX_ARG_BYTES: usize = 128 * 1024 * 1024;
+
+/// Speaks just enough of the Redis protocol on localhost for
+/// `RedisBackend`: AUTH, SELECT, GET, SET with PX, GETRANGE, STRLEN, PTTL,
+/// DEL, SCAN with MATCH and INFO. SCAN hands out a couple of keys at a time
+/// so callers have to follow the cursor. Keys expire lazily, when next
+/// touched.
+pub struct FakeRedis {
+    addr: SocketAddr,
+    state: Arc<Mutex<State>>,
+    task: JoinHandle<()>,
+}
+
+#[derive(Default)]
+struct State {
+    password: Option<String>,
+    values: HashMap<Vec<u8>, (Vec<u8>, Option<Instant>)>,
+    expired: u64,
+    commands: Vec<String>,
+    /// The last key each SCAN cursor handed out, so keys deleted along the
+    /// way do not make a scan skip others. Cursor `n` is `scans[n - 1]`.
+    scans: Vec<Vec<u8>>,
+}
+
+impl State {
+    fn live(&mut self, key: &[u8]) -> Option<&Vec<u8>> {
+        let expired = self
+            .values
+            .get(key)
+            .is_some_and(|(_, expires_at)| expires_at.is_some_and(|at| at <= Instant::now()));
+        if expired {
+            self.values.remove(key);
+            self.expired += 1;
+        }
+        self.values.get(key).map(|(value, _)| value)
+    }
+}
+
+impl FakeRedis {
+    pub async fn start() -> Self {
+        Self::start_with_password(None).await
+    }
+
+    pub async fn start_with_password(password: Option<&str>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let state = Arc::new(Mutex::new(State {
+            password: password.map(str::to_string),
+            ..State::default()
+        }));
+        let accepting = state.clone();
+        let task = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(serve(stream, accepting.clone()));
+            }
+        });
+        Self { addr, state, task }
+    }
+
+    pub fn url(&self) -> String {
+        format!("redis://{}", self.addr)
+    }
+
+    /// Command names in the order they were received.
+    pub fn commands(&self) -> Vec<String> {
+        self.state.lock().unwrap().commands.clone()
+    }
+
+    pub fn raw(&self, key: &str) -> Option<Vec<u8>> {
+        self.state.lock().unwrap().live(key.as_bytes()).cloned()
+    }
+}
+
+impl Drop for FakeRedis {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// A RESP2 reply.
+enum Reply {
+    Status(&'static str),
+    Error(String),
+    Integer(i64),
+    Bulk(Vec<u8>),
+    Nil,
+    Array(Vec<Reply>),
+}
+
+impl Reply {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            Reply::Status(status) => out.extend_from_slice(format!("+{}\r\n", status).as_bytes()),
+            Reply::Error(error) => out.extend_from_slice(format!("-{}\r\n", error).as_bytes()),
+            Reply::Integer(n) => out.extend_from_slice(format!(":{}\r\n", n).as_bytes()),
+            Reply::Bulk(bytes) => {
+                out.extend_from_slice(format!("${}\r\n", bytes.len()).as_bytes());
+                out.extend_from_slice(bytes);
+                out.extend_from_slice(b"\r\n");
+            }
+            Reply::Nil => out.extend_from_slice(b"$-1\r\n"),
+            Reply::Array(items) => {
+                out.extend_from_slice(format!("*{}\r\n", items.len()).as_bytes());
+                for item in items {
+                    item.encode(out);
+                }
+            }
+        }
+    }
+}
+
+async fn serve(stream: TcpStream, state: Arc<Mutex<State>>) {
+    let mut stream = BufReader::new(stream);
+    let mut authenticated = state.lock().unwrap().password.is_none();
+    while let Some(args) = read_command(&mut stream).await {
+        let reply = execute(&args, &mut authenticated, &mut state.lock().unwrap());
+        let mut out = Vec::new();
+        reply.encode(&mut out);
+        if stream.get_mut().write_all(&out).await.is_err() {
+            return;
+        }
+    }
+}
+
+/// Reads one command, an array of bulk strings. `None` once the client
+/// hangs up or sends anything else.
+async fn read_command(stream: &mut BufReader<TcpStream>) -> Option<Vec<Vec<u8>>> {
+    let count = read_length(stream, b'*').await?;
+    let mut args = Vec::with_capacity(count.min(64));
+    for _ in 0..count {
+        let len = read_length(stream, b'$').await?;
+        if len > MAX_ARG_BYTES {
+            return None;
+        }
+        let mut arg = vec![0; len + 2];
+        stream.read_exact(&mut arg).await.ok()?;
+        arg.truncate(len);
+        args.push(arg);
+    }
+    Some(args)
+}
+
+async fn read_length(stream: &mut BufReader<TcpStream>, kind: u8) -> Option<usize> {
+    let mut line = Vec::new();
+    stream.read_until(b'\n', &mut line).await.ok()?;
+    let digits = line.strip_prefix(&[kind])?.strip_suffix(b"\r\n")?;
+    std::str::from_utf8(digits).ok()?.parse().ok()
+}
+
+fn execute(args: &[Vec<u8>], authenticated: &mut bool, state: &mut State) -> Reply {
+    let Some(name) = args.first().map(|name| String::from_utf8_lossy(name).to_uppercase()) else {
+        return Reply::Error("ERR empty command".to_string());
+    };
+    state.commands.push(name.clone());
+    let text = |i: usize| String::from_utf8_lossy(&args[i]).into_owned();
+
+    if name == "AUTH" {
+        let given = args.last().map(|password| String::from_utf8_lossy(password).into_owned());
+        *authenticated = given == state.password;
+        return if *authenticated {
+            Reply::Status("OK")
+        } else {
+            Reply::Error("WRONGPASS invalid password".to_string())
+        };
+    }
+    if !*authenticated {
+        return Reply::Error("NOAUTH Authentication required.".to_string());
+    }
+
+    match (name.as_str(), args.len()) {
+        ("SELECT", 2) => Reply::Status("OK"),
+        ("GET", 2) => state.live(&args[1]).cloned().map_or(Reply::Nil, Reply::Bulk),
+        ("SET", 3) | ("SET", 5) => {
+            let expires_at = match args.get(4) {
+                Some(ms) => match text(4).parse::<u64>() {
+                    Ok(ms) if ms > 0 => Some(Instant::now() + Duration::from_millis(ms)),
+                    _ => return Reply::Error(format!("ERR invalid expire time {:?}", ms)),
+                },
+                None => None,
+            };
+            state.values.insert(args[1].clone(), (args[2].clone(), expires_at));
+            Reply::Status("OK")
+        }
+        ("GETRANGE", 4) => {
+            let value = state.live(&args[1]).cloned().unwrap_or_default();
+            let (start, end): (usize, usize) = (text(2).parse().unwrap(), text(3).parse().unwrap());
+            let end = end.saturating_add(1).min(value.len());
+            Reply::Bulk(value.get(start..end).unwrap_or_default().to_vec())
+        }
+        ("STRLEN", 2) => Reply::Integer(state.live(&args[1]).map_or(0, Vec::len) as i64),
+        ("PTTL", 2) => match state.live(&args[1]).is_some().then(|| state.values[&args[1]].1) {
+            None => Reply::Integer(-2),
+            Some(None) => Reply::Integer(-1),
+            Some(Some(at)) => Reply::Integer(at.saturating_duration_since(Instant::now()).as_millis() as i64),
+        },
+        ("DEL", _) => {
+            let removed = args[1..]
+                .iter()
+                .filter(|key| state.live(key).is_some() && state.values.remove(*key).is_some())
+                .count();
+            Reply::Integer(removed as i64)
+        }
+        ("SCAN", 6) => {
+            let cursor: usize = text(1).parse().unwrap_or(0);
+            let after = cursor.checked_sub(1).and_then(|i| state.scans.get(i)).cloned();
+            let mut keys: Vec<Vec<u8>> = state
+                .values
+                .keys()
+                .filter(|key| after.as_ref().is_none_or(|after| *key > after))
+                .cloned()
+                .collect();
+            keys.sort();
+            let next = match keys.get(SCAN_BATCH) {
+                Some(_) => {
+                    state.scans.push(keys[SCAN_BATCH - 1].clone());
+                    state.scans.len()
+                }
+                None => 0,
+            };
+            let matching = keys
+                .into_iter()
+                .take(SCAN_BATCH)
+                .filter(|key| glob_match(&args[3], key) && state.live(key).is_some())
+                .map(Reply::Bulk)
+                .collect();
+            Reply::Array(vec![Reply::Bulk(next.to_string().into_bytes()), Reply::Array(matching)])
+        }
+        ("INFO", _) => Reply::Bulk(format!("# Stats\r\nexpired_keys:{}\r\nevicted_keys:0\r\n", state.expired).into_bytes()),
+        _ => Reply::Error(format!("ERR unsupported command {}", name)),
+    }
+}
+
+/// `*`, `?` and backslash escapes, which is all `RedisBackend` sends.
+fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some((b'*', rest)) => (0..=text.len()).any(|skip| glob_match(rest, &text[skip..])),
+        Some((b'?', rest)) => !text.is_empty() && glob_match(rest, &text[1..]),
+        Some((b'\\', [escaped, rest @ ..])) => text.first() == Some(escaped) && glob_match(rest, &text[1..]),
+        Some((c, rest)) => text.first() == Some(c) && glob_match(rest, &text[1..]),
+    }
+}
@@ -0,0 +1,188 @@
+use std::borrow::Borrow;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+
+use super::CacheLimits;
+
+/// Entries with their approximate size in bytes, indexed by when they were
+/// last used so the least recently used one is found without a scan.
+pub struct Lru<K, V> {
+    slots: HashMap<K, Slot<V>>,
+    /// Keys by the tick they were last used at, oldest first.
+    by_use: BTreeMap<u64, K>,
+    clock: u64,
+    bytes: usize,
+}
+
+struct Slot<V> {
+    value: V,
+    bytes: usize,
+    used: u64,
+}
+
+impl<K: Clone + Eq + Hash, V> Lru<K, V> {
+    pub fn new() -> Self {
+        Self {
+            slots: HashMap::new(),
+            by_use: BTreeMap::new(),
+            clock: 0,
+            bytes: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /// Marks the entry as the most recently used.
+    pub fn get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.clock += 1;
+        let slot = self.slots.get_mut(key)?;
+        let key = self.by_use.remove(&slot.used)?;
+        slot.used = self.clock;
+        self.by_use.insert(self.clock, key);
+        Some(&slot.value)
+    }
+
+    /// Looks at the entry without marking it used.
+    pub fn peek<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.slots.get(key).map(|slot| &slot.value)
+    }
+
+    /// Adds or replaces the entry as the most recently used.
+    pub fn insert(&mut self, key: K, value: V, bytes: usize) {
+        self.remove(&key);
+        self.clock += 1;
+        self.by_use.insert(self.clock, key.clone());
+        self.slots.insert(key, Slot {
+            value,
+            bytes,
+            used: self.clock,
+        });
+        self.bytes += bytes;
+    }
+
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        let slot = self.slots.remove(key)?;
+        self.by_use.remove(&slot.used);
+        self.bytes -= slot.bytes;
+        Some(slot.value)
+    }
+
+    /// Evicts the least recently used entries until both limits are met,
+    /// so an entry over the byte budget on its own is not kept. Returns how
+    /// many were evicted.
+    pub fn evict_past(&mut self, limits: CacheLimits) -> usize {
+        let mut evicted = 0;
+        while self.slots.len() > limits.max_entries || self.bytes > limits.max_bytes {
+            let Some((_, key)) = self.by_use.pop_first() else {
+                break;
+            };
+            if let Some(slot) = self.slots.remove(&key) {
+                self.bytes -= slot.bytes;
+            }
+            evicted += 1;
+        }
+        evicted
+    }
+
+    /// Drops the entries `keep` rejects. Returns how many were dropped.
+    pub fn retain(&mut self, mut keep: impl FnMut(&K, &V) -> bool) -> usize {
+        let dropped: Vec<K> = self
+            .slots
+            .iter()
+            .filter(|(key, slot)| !keep(key, &slot.value))
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in &dropped {
+            self.remove(key);
+        }
+        dropped.len()
+    }
+
+    /// Every entry with its size, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V, usize)> {
+        self.slots.iter().map(|(key, slot)| (key, &slot.value, slot.bytes))
+    }
+
+    pub fn clear(&mut self) {
+        self.slots.clear();
+        self.by_use.clear();
+        self.bytes = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits(max_entries: usize, max_bytes: usize) -> CacheLimits {
+        CacheLimits { max_entries, max_bytes }
+    }
+
+    #[test]
+    fn evicts_least_recently_used_first() {
+        let mut lru = Lru::new();
+        lru.insert("a", 1, 10);
+        lru.insert("b", 2, 10);
+        lru.insert("c", 3, 10);
+        lru.get("a");
+
+        assert_eq!(lru.evict_past(limits(2, usize::MAX)), 1);
+        assert!(lru.peek("b").is_none());
+        assert_eq!((lru.peek("a"), lru.peek("c")), (Some(&1), Some(&3)));
+    }
+
+    #[test]
+    fn peek_does_not_count_as_use() {
+        let mut lru = Lru::new();
+        lru.insert("a", 1, 10);
+        lru.insert("b", 2, 10);
+        lru.peek("a");
+
+        lru.evict_past(limits(1, usize::MAX));
+
+        assert!(lru.peek("a").is_none());
+    }
+
+    #[test]
+    fn tracks_bytes_through_replace_and_remove() {
+        let mut lru = Lru::new();
+        lru.insert("a", 1, 10);
+        lru.insert("b", 2, 20);
+        lru.insert("a", 3, 5);
+        assert_eq!((lru.len(), lru.bytes()), (2, 25));
+
+        assert_eq!(lru.remove("b"), Some(2));
+        assert_eq!(lru.bytes(), 5);
+        assert_eq!(lru.evict_past(limits(10, 4)), 1);
+        assert_eq!((lru.len(), lru.bytes()), (0, 0));
+    }
+
+    #[test]
+    fn retain_drops_rejected_entries() {
+        let mut lru = Lru::new();
+        lru.insert("a", 1, 10);
+        lru.insert("b", 2, 10);
+
+        assert_eq!(lru.retain(|_, value| *value > 1), 1);
+        assert_eq!((lru.len(), lru.bytes()), (1, 10));
+        assert_eq!(lru.evict_past(limits(0, usize::MAX)), 1);
+    }
+}
@@ -0,0 +1,314 @@
+use futures_util::future::BoxFuture;
+use futures_util::FutureExt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+use super::lru::Lru;
+use super::{BackendStats, CacheBackend, CacheEntry, CacheLimits, CachedHistory, CachedHistoryInfo};
+use crate::crypto::HistoryKey;
+use crate::models::HistoryEntry;
+
+const SWEEP_INTERVAL: Duration = Duration::from_secs(5 * 60); // 5 minutes
+
+/// A sealed history and how many entries it holds.
+struct HistorySlot {
+    entry: CacheEntry<Arc<[u8]>>,
+    items: usize,
+}
+
+/// Histories in this process, bounded by `CacheLimits` and swept every
+/// `SWEEP_INTERVAL`. Like the store, it only holds histories sealed with
+/// their owner's `HistoryKey`, bound to the cache key, so a memory dump
+/// without live sessions shows no one's history.
+pub struct MemoryBackend {
+    history: Mutex<Lru<String, HistorySlot>>,
+    limits: CacheLimits,
+    evictions: AtomicU64,
+    expirations: AtomicU64,
+}
+
+impl MemoryBackend {
+    pub fn new(limits: CacheLimits) -> Arc<Self> {
+        let backend = Arc::new(Self {
+            history: Mutex::new(Lru::new()),
+            limits,
+            evictions: AtomicU64::new(0),
+            expirations: AtomicU64::new(0),
+        });
+
+        // Periodic removal of histories too old to serve
+        let backend_clone = backend.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(SWEEP_INTERVAL).await;
+                backend_clone.sweep().await;
+            }
+        });
+
+        backend
+    }
+
+    /// Drops the history if it is too old to serve. It is opened after the
+    /// lock is released.
+    async fn find(&self, key: &str, sealing_key: &HistoryKey) -> anyhow::Result<Option<CachedHistory>> {
+        let (sealed, age, stale) = {
+            let mut cache = self.history.lock().await;
+            if cache.peek(key).is_some_and(|slot| slot.entry.is_too_old()) {
+                cache.remove(key);
+                self.expirations.fetch_add(1, Ordering::Relaxed);
+                return Ok(None);
+            }
+            let Some(slot) = cache.get(key) else {
+                return Ok(None);
+            };
+            (slot.entry.data.clone(), slot.entry.inserted_at.elapsed(), slot.entry.is_expired())
+        };
+
+        let plaintext = match sealing_key.open(key, &sealed) {
+            Ok(plaintext) => plaintext,
+            Err(error) => {
+                // Most likely sealed before a password change.
+                tracing::warn!(event = "history_cache_unreadable", error = %error);
+                return Ok(None);
+            }
+        };
+        Ok(Some(CachedHistory {
+            data: serde_json::from_slice(&plaintext)?,
+            age,
+            stale,
+        }))
+    }
+
+    async fn insert(
+        &self,
+        key: String,
+        sealing_key: &HistoryKey,
+        data: &[HistoryEntry],
+        age: Duration,
+        ttl: Duration,
+        max_age: Duration,
+    ) -> anyhow::Result<()> {
+        let sealed = sealing_key.seal(&key, &serde_json::to_vec(data)?)?;
+        let bytes = sealed.len();
+        let mut cache = self.history.lock().await;
+        cache.insert(key, HistorySlot {
+            entry: CacheEntry {
+                data: sealed.into(),
+                inserted_at: Instant::now().checked_sub(age).unwrap_or_else(Instant::now),
+                ttl,
+                max_age,
+            },
+            items: data.len(),
+        }, bytes);
+
+        let evicted = cache.evict_past(self.limits);
+        if evicted > 0 {
+            self.evictions.fetch_add(evicted as u64, Ordering::Relaxed);
+            tracing::debug!(event = "history_cache_eviction", evicted);
+        }
+        Ok(())
+    }
+
+    /// Drops histories past their maximum age.
+    async fn sweep(&self) {
+        let expired = self.history.lock().await.retain(|_, slot| !slot.entry.is_too_old());
+        self.expirations.fetch_add(expired as u64, Ordering::Relaxed);
+    }
+}
+
+impl CacheBackend for MemoryBackend {
+    fn get<'a>(
+        &'a self,
+        key: &'a str,
+        sealing_key: &'a HistoryKey,
+    ) -> BoxFuture<'a, anyhow::Result<Option<CachedHistory>>> {
+        self.find(key, sealing_key).boxed()
+    }
+
+    fn set<'a>(
+        &'a self,
+        key: String,
+        sealing_key: &'a HistoryKey,
+        data: Vec<HistoryEntry>,
+        age: Duration,
+        ttl: Duration,
+        max_age: Duration,
+    ) -> BoxFuture<'a, anyhow::Result<()>> {
+        async move { self.insert(key, sealing_key, &data, age, ttl, max_age).await }.boxed()
+    }
+
+    fn entries(&self) -> BoxFuture<'_, anyhow::Result<Vec<CachedHistoryInfo>>> {
+        async move {
+            let cache = self.history.lock().await;
+            Ok(cache
+                .iter()
+                .map(|(key, slot, bytes)| CachedHistoryInfo {
+                    key: key.clone(),
+                    age: slot.entry.inserted_at.elapsed(),
+                    stale: slot.entry.is_expired(),
+                    items: slot.items,
+                    bytes,
+                })
+                .collect())
+        }
+        .boxed()
+    }
+
+    fn remove_prefix<'a>(&'a self, prefix: &'a str) -> BoxFuture<'a, anyhow::Result<usize>> {
+        async move {
+            let mut cache = self.history.lock().await;
+            Ok(cache.retain(|key, _| !key.starts_with(prefix)))
+        }
+        .boxed()
+    }
+
+    fn stats(&self) -> BoxFuture<'_, anyhow::Result<BackendStats>> {
+        async move {
+            Ok(BackendStats {
+                evictions: self.evictions.load(Ordering::Relaxed),
+                expirations: self.expirations.load(Ordering::Relaxed),
+            })
+        }
+        .boxed()
+    }
+
+    fn limits(&self) -> Option<CacheLimits> {
+        Some(self.limits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::{HISTORY_MAX_STALENESS, HISTORY_TTL};
+
+    async fn set(backend: &MemoryBackend, key: &str, id: &str) {
+        insert(backend, key, id, HISTORY_TTL, HISTORY_MAX_STALENESS).await;
+    }
+
+    async fn insert(backend: &MemoryBackend, key: &str, id: &str, ttl: Duration, max_age: Duration) {
+        backend
+            .insert(key.to_string(), &HistoryKey::test(), &[HistoryEntry::test(id, None)], Duration::ZERO, ttl, max_age)
+            .await
+            .unwrap();
+    }
+
+    async fn find(backend: &MemoryBackend, key: &str) -> Option<CachedHistory> {
+        backend.find(key, &HistoryKey::test()).await.unwrap()
+    }
+
+    async fn stats(backend: &MemoryBackend) -> BackendStats {
+        backend.stats().await.unwrap()
+    }
+
+    /// How many histories are held and their bytes.
+    async fn size(backend: &MemoryBackend) -> (usize, usize) {
+        let cache = backend.history.lock().await;
+        (cache.len(), cache.bytes())
+    }
+
+    #[tokio::test]
+    async fn least_recently_used_history_is_evicted_past_max_entries() {
+        let backend = MemoryBackend::new(CacheLimits {
+            max_entries: 2,
+            ..CacheLimits::default()
+        });
+        set(&backend, "key1", "item-1").await;
+        set(&backend, "key2", "item-2").await;
+        find(&backend, "key1").await.unwrap();
+
+        set(&backend, "key3", "item-3").await;
+
+        assert!(find(&backend, "key1").await.is_some());
+        assert!(find(&backend, "key2").await.is_none());
+        assert!(find(&backend, "key3").await.is_some());
+        assert_eq!(stats(&backend).await.evictions, 1);
+    }
+
+    #[tokio::test]
+    async fn histories_are_evicted_to_stay_within_the_byte_budget() {
+        let json = serde_json::to_vec(&vec![HistoryEntry::test("item-0", None)]).unwrap();
+        let entry_bytes = HistoryKey::test().seal("key1", &json).unwrap().len();
+        let backend = MemoryBackend::new(CacheLimits {
+            max_bytes: entry_bytes * 2,
+            ..CacheLimits::default()
+        });
+        set(&backend, "key1", "item-0").await;
+        set(&backend, "key2", "item-1").await;
+        assert_eq!(size(&backend).await.1, entry_bytes * 2);
+
+        set(&backend, "key3", "item-2").await;
+
+        assert_eq!(size(&backend).await, (2, entry_bytes * 2));
+        assert_eq!(stats(&backend).await.evictions, 1);
+        assert!(find(&backend, "key1").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn history_over_the_byte_budget_is_not_kept() {
+        let backend = MemoryBackend::new(CacheLimits {
+            max_bytes: 10,
+            ..CacheLimits::default()
+        });
+        set(&backend, "key1", "item-0").await;
+
+        assert!(find(&backend, "key1").await.is_none());
+        assert_eq!(stats(&backend).await.evictions, 1);
+    }
+
+    #[tokio::test]
+    async fn replacing_a_history_keeps_the_byte_count() {
+        let backend = MemoryBackend::new(CacheLimits::default());
+        set(&backend, "key1", "item-0").await;
+        let bytes = size(&backend).await.1;
+
+        set(&backend, "key1", "item-1").await;
+
+        assert_eq!(size(&backend).await.1, bytes);
+    }
+
+    #[tokio::test]
+    async fn sweep_drops_histories_too_old_to_serve() {
+        let backend = MemoryBackend::new(CacheLimits::default());
+        insert(&backend, "key1", "item-0", Duration::ZERO, Duration::ZERO).await;
+        insert(&backend, "key2", "item-1", Duration::ZERO, HISTORY_MAX_STALENESS).await;
+
+        backend.sweep().await;
+
+        assert_eq!(size(&backend).await.0, 1);
+        assert_eq!(stats(&backend).await.expirations, 1);
+        assert!(find(&backend, "key2").await.is_some());
+    }
+
+    #[tokio::test]
+    async fn remove_prefix_drops_matching_keys_and_their_bytes() {
+        let backend = MemoryBackend::new(CacheLimits::default());
+        set(&backend, "alice:a", "item-0").await;
+        set(&backend, "alice:b", "item-1").await;
+        set(&backend, "bob:a", "item-2").await;
+
+        assert_eq!(backend.remove_prefix("alice:").await.unwrap(), 2);
+
+        let entries = backend.entries().await.unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(size(&backend).await.1, entries[0].bytes);
+    }
+
+    #[tokio::test]
+    async fn histories_are_held_sealed() {
+        let backend = MemoryBackend::new(CacheLimits::default());
+        set(&backend, "key1", "item-0").await;
+
+        {
+            let cache = backend.history.lock().await;
+            let sealed = String::from_utf8_lossy(&cache.peek("key1").unwrap().entry.data).into_owned();
+            assert!(!sealed.contains("item-0") && !sealed.contains("Frieren"));
+        }
+        assert_eq!(find(&backend, "key1").await.unwrap().data[0].id, "item-0");
+        let other_key = HistoryKey::from_bytes([8; 32]);
+        assert!(backend.find("key1", &other_key).await.unwrap().is_none());
+        assert_eq!(backend.entries().await.unwrap()[0].items, 1);
+    }
+}
@@ -0,0 +1,878 @@
+mod lru;
+pub mod memory;
+pub mod redis;
+#[cfg(test)]
+pub mod fake_redis;
+
+use anyhow::anyhow;
+use futures_util::future::BoxFuture;
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::Hash;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use serde::Serialize;
+use tokio::sync::watch;
+
+use crate::crypto::HistoryKey;
+use crate::history::{HistoryWindow, SeriesCatalogue};
+use crate::models::{Genre, HistoryEntry, Image, MediaKind};
+use lru::Lru;
+use memory::MemoryBackend;
+use self::redis::RedisBackend;
+
+pub const HISTORY_TTL: Duration = Duration::from_secs(60 * 60); // 60 minutes
+pub const HISTORY_MAX_STALENESS: Duration = Duration::from_secs(24 * 60 * 60); // served while refreshing up to this age
+pub const METADATA_TTL: Duration = Duration::from_secs(24 * 60 * 60); // 24 hours
+const METADATA_SWEEP_INTERVAL: Duration = Duration::from_secs(60 * 60); // 1 hour
+const DEFAULT_MAX_ENTRIES: usize = 1000;
+const DEFAULT_MAX_BYTES: usize = 256 * 1024 * 1024; // 256 MiB
+
+struct CacheEntry<T> {
+    data: T,
+    inserted_at: Instant,
+    ttl: Duration,
+    /// How long the entry may be served stale, counted like `ttl`.
+    max_age: Duration,
+}
+
+impl<T> CacheEntry<T> {
+    fn is_expired(&self) -> bool {
+        self.inserted_at.elapsed() > self.ttl
+    }
+
+    fn is_too_old(&self) -> bool {
+        self.inserted_at.elapsed() > self.max_age
+    }
+}
+
+/// Caps on the in-memory history cache, and on each kind of shared metadata.
+/// Past either, the least recently used entries are evicted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheLimits {
+    pub max_entries: usize,
+    /// Budget for the entries' size, as JSON for metadata and sealed for
+    /// histories, which is close to what they take in memory.
+    pub max_bytes: usize,
+}
+
+impl Default for CacheLimits {
+    fn default() -> Self {
+        Self {
+            max_entries: DEFAULT_MAX_ENTRIES,
+            max_bytes: DEFAULT_MAX_BYTES,
+        }
+    }
+}
+
+impl CacheLimits {
+    /// Reads `CACHE_MAX_ENTRIES` and `CACHE_MAX_BYTES`, falling back to the
+    /// defaults for unset ones.
+    pub fn from_env() -> anyhow::Result<Self> {
+        let defaults = Self::default();
+        Ok(Self {
+            max_entries: limit_from_env("CACHE_MAX_ENTRIES", defaults.max_entries)?,
+            max_bytes: limit_from_env("CACHE_MAX_BYTES", defaults.max_bytes)?,
+        })
+    }
+}
+
+fn limit_from_env(name: &str, default: usize) -> anyhow::Result<usize> {
+    match std::env::var(name) {
+        Ok(raw) => raw
+            .parse()
+            .ok()
+            .filter(|limit| *limit > 0)
+            .ok_or_else(|| anyhow!("{} must be a positive integer, got {:?}", name, raw)),
+        Err(_) => Ok(default),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HistoryCacheStats {
+    pub entries: usize,
+    pub bytes: usize,
+    pub hits: u64,
+    /// Lookups that found nothing, or a history too old to serve.
+    pub misses: u64,
+    /// Histories dropped to stay within the limits.
+    pub evictions: u64,
+    /// Histories dropped for being past `HISTORY_MAX_STALENESS`.
+    pub expirations: u64,
+}
+
+/// Counters a backend keeps about its own contents. Hits and misses are
+/// counted by `AppCache`, per process, and sizes come from `entries`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BackendStats {
+    pub evictions: u64,
+    pub expirations: u64,
+}
+
+/// What is cached under one key, without the history itself.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CachedHistoryInfo {
+    pub key: String,
+    pub age: Duration,
+    pub stale: bool,
+    pub items: usize,
+    pub bytes: usize,
+}
+
+/// A cached history with how long ago it was cached. Stale entries are
+/// past `HISTORY_TTL` and should be refreshed.
+#[derive(Debug, Clone)]
+pub struct CachedHistory {
+    pub data: Vec<HistoryEntry>,
+    pub age: Duration,
+    pub stale: bool,
+}
+
+/// Where `AppCache` keeps histories: `MemoryBackend` in this process, or
+/// `RedisBackend` on a server shared by every replica. Keys are
+/// `AppCache::history_key`s. `sealing_key` is the owner's `HistoryKey`, for
+/// backends that keep histories outside the process and must not hold them
+/// in plain text.
+pub trait CacheBackend: Send + Sync {
+    /// The history under `key`, unless it is past the `max_age` it was set
+    /// with.
+    fn get<'a>(
+        &'a self,
+        key: &'a str,
+        sealing_key: &'a HistoryKey,
+    ) -> BoxFuture<'a, anyhow::Result<Option<CachedHistory>>>;
+
+    /// `age` is how long ago `data` was fetched; `ttl` and `max_age` count
+    /// from then.
+    fn set<'a>(
+        &'a self,
+        key: String,
+        sealing_key: &'a HistoryKey,
+        data: Vec<HistoryEntry>,
+        age: Duration,
+        ttl: Duration,
+        max_age: Duration,
+    ) -> BoxFuture<'a, anyhow::Result<()>>;
+
+    /// Every cached history, in no particular order.
+    fn entries(&self) -> BoxFuture<'_, anyhow::Result<Vec<CachedHistoryInfo>>>;
+
+    /// Drops the histories whose keys start with `prefix`. Returns how many
+    /// were removed.
+    fn remove_prefix<'a>(&'a self, prefix: &'a str) -> BoxFuture<'a, anyhow::Result<usize>>;
+
+    fn stats(&self) -> BoxFuture<'_, anyhow::Result<BackendStats>>;
+
+    /// The limits enforced by this process, if any.
+    fn limits(&self) -> Option<CacheLimits>;
+}
+
+/// Cache isolated per user.
+/// Cache keys are SHA256 hashes of the user's email address, ensuring:
+/// - Each user's data is stored under a unique, non-reversible key
+/// - No scenario where user A's lookup can return user B's data
+/// - Email addresses are not stored as plain text in cache keys
+///
+/// Series and movie listing metadata is not user data and is shared by
+/// everyone, see `MetadataCache`.
+///
+/// Histories are kept by a `CacheBackend`. A backend that fails is logged
+/// and treated as a miss, so requests fall through to the store and
+/// Crunchyroll. Fetches are only coalesced within one process.
+pub struct AppCache {
+    history: Arc<dyn CacheBackend>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    fetches: Mutex<HashMap<String, watch::Receiver<Option<FetchResult>>>>,
+    metadata: Arc<MetadataCache>,
+}
+
+/// The outcome of a history fetch as handed to the requests waiting on it.
+type FetchResult = Result<Vec<HistoryEntry>, String>;
+
+impl AppCache {
+    #[cfg(test)]
+    pub fn new() -> Arc<Self> {
+        Self::with_backend(MemoryBackend::new(CacheLimits::default()), CacheLimits::default())
+    }
+
+    /// `limits` bound the shared metadata; the backend bounds histories.
+    pub fn with_backend(history: Arc<dyn CacheBackend>, limits: CacheLimits) -> Arc<Self> {
+        Arc::new(Self {
+            history,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            fetches: Mutex::new(HashMap::new()),
+            metadata: MetadataCache::new(limits),
+        })
+    }
+
+    /// Picks the history backend from `CACHE_BACKEND`: `memory`, bounded
+    /// by `CacheLimits::from_env`, or `redis` at `REDIS_URL`.
+    pub fn from_env() -> anyhow::Result<Arc<Self>> {
+        let kind = std::env::var("CACHE_BACKEND").unwrap_or_else(|_| "memory".to_string());
+        let limits = CacheLimits::from_env()?;
+        let history: Arc<dyn CacheBackend> = match kind.as_str() {
+            "memory" => MemoryBackend::new(limits),
+            "redis" => {
+                let url = std::env::var("REDIS_URL")
+                    .unwrap_or_else(|_| "redis://127.0.0.1:6379".to_string());
+                RedisBackend::new(&url)?
+            }
+            other => return Err(anyhow!("unknown CACHE_BACKEND {:?}, expected memory or redis", other)),
+        };
+        Ok(Self::with_backend(history, limits))
+    }
+
+    pub fn metadata(&self) -> &MetadataCache {
+        &self.metadata
+    }
+
+    pub fn cache_key(email: &str) -> String {
+        use sha2::{Digest, Sha256};
+        let mut hasher = Sha256::new();
+        hasher.update(email.as_bytes());
+        hex::encode(hasher.finalize())
+    }
+
+    /// Key for one user's history over one window, so different windows
+    /// never share an entry.
+    pub fn history_key(user_key: &str, window: &HistoryWindow) -> String {
+        format!("{}:{}", user_key, window)
+    }
+
+    pub fn limits(&self) -> Option<CacheLimits> {
+        self.history.limits()
+    }
+
+    /// The cached history, stale or not, unless it is older than
+    /// `HISTORY_MAX_STALENESS`. `sealing_key` is the owner's `HistoryKey`.
+    pub async fn get_history(&self, key: &str, sealing_key: &HistoryKey) -> Option<CachedHistory> {
+        let found = match self.history.get(key, sealing_key).await {
+            Ok(found) => found,
+            Err(error) => {
+                tracing::warn!(event = "history_cache_read_failed", error = %error);
+                None
+            }
+        };
+        let counter = if found.is_some() { &self.hits } else { &self.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+        found
+    }
+
+    pub async fn set_history(&self, key: String, sealing_key: &HistoryKey, data: Vec<HistoryEntry>) {
+        self.insert_history(key, sealing_key, data, Duration::ZERO, HISTORY_TTL, HISTORY_MAX_STALENESS).await;
+    }
+
+    /// Caches a history synced `age` ago, such as one read from the store,
+    /// so it turns stale and is reported as old as it really is.
+    pub async fn set_synced_history(&self, key: String, sealing_key: &HistoryKey, data: Vec<HistoryEntry>, age: Duration) {
+        self.insert_history(key, sealing_key, data, age, HISTORY_TTL, HISTORY_MAX_STALENESS).await;
+    }
+
+    #[cfg(test)]
+    pub async fn history_stats(&self) -> anyhow::Result<HistoryCacheStats> {
+        Ok(self.history_report().await?.0)
+    }
+
+    /// Every cached history, oldest first.
+    #[cfg(test)]
+    pub async fn history_entries(&self) -> anyhow::Result<Vec<CachedHistoryInfo>> {
+        Ok(self.history_report().await?.1)
+    }
+
+    /// The stats and every cached history, oldest first, counted from one
+    /// listing of the backend.
+    pub async fn history_report(&self) -> anyhow::Result<(HistoryCacheStats, Vec<CachedHistoryInfo>)> {
+        let (mut entries, backend) = tokio::try_join!(self.history.entries(), self.history.stats())?;
+        entries.sort_by_key(|entry| std::cmp::Reverse(entry.age));
+        let stats = HistoryCacheStats {
+            entries: entries.len(),
+            bytes: entries.iter().map(|entry| entry.bytes).sum(),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: backend.evictions,
+            expirations: backend.expirations,
+        };
+        Ok((stats, entries))
+    }
+
+    /// Drops every window cached for `user_key`, as from `cache_key`.
+    /// Returns how many histories were removed.
+    pub async fn invalidate_user(&self, user_key: &str) -> anyhow::Result<usize> {
+        self.history.remove_prefix(&format!("{}:", user_key)).await
+    }
+
+    /// Drops every history and all shared metadata. Returns how many
+    /// histories were removed.
+    pub async fn flush(&self) -> anyhow::Result<usize> {
+        let removed = self.history.remove_prefix("").await?;
+        self.metadata.clear().await;
+        Ok(removed)
+    }
+
+    async fn insert_history(
+        &self,
+        key: String,
+        sealing_key: &HistoryKey,
+        data: Vec<HistoryEntry>,
+        age: Duration,
+        ttl: Duration,
+        max_age: Duration,
+    ) {
+        if let Err(error) = self.history.set(key, sealing_key, data, age, ttl, max_age).await {
+            tracing::warn!(event = "history_cache_write_failed", error = %error);
+        }
+    }
+
+    /// Runs `fetch` for `key` unless a fetch for the same key is already
+    /// running, in which case that one's result or error is returned
+    /// instead, so concurrent misses cost one upstream fetch. If the running
+    /// fetch is dropped before it finishes, a waiting request runs its own.
+    pub async fn single_flight<F>(&self, key: &str, fetch: F) -> anyhow::Result<Vec<HistoryEntry>>
+    where
+        F: Future<Output = anyhow::Result<Vec<HistoryEntry>>>,
+    {
+        loop {
+            let flight = {
+                let mut fetches = self.fetches.lock().unwrap();
+                match fetches.get(key) {
+                    Some(receiver) => Flight::Join(receiver.clone()),
+                    None => {
+                        let (sender, receiver) = watch::channel(None);
+                        fetches.insert(key.to_string(), receiver);
+                        Flight::Lead(sender)
+                    }
+                }
+            };
+            let mut running = match flight {
+                Flight::Join(receiver) => receiver,
+                Flight::Lead(sender) => return self.lead(key, sender, fetch).await,
+            };
+
+            tracing::debug!(event = "history_fetch_joined");
+            let finished = running
+                .wait_for(Option::is_some)
+                .await
+                .map(|result| result.clone().expect("waited for a result"));
+            if let Ok(result) = finished {
+                return result.map_err(|error| anyhow!(error));
+            }
+            // The running fetch was dropped; try again, most likely leading.
+        }
+    }
+
+    async fn lead<F>(
+        &self,
+        key: &str,
+        sender: watch::Sender<Option<FetchResult>>,
+        fetch: F,
+    ) -> anyhow::Result<Vec<HistoryEntry>>
+    where
+        F: Future<Output = anyhow::Result<Vec<HistoryEntry>>>,
+    {
+        // Also clears the key when this request is dropped mid-fetch.
+        let _running = RunningFetch { fetches: &self.fetches, key };
+        let result = fetch.await;
+        sender.send_replace(Some(match &result {
+            Ok(entries) => Ok(entries.clone()),
+            Err(error) => Err(format!("{:#}", error)),
+        }));
+        result
+    }
+
+    #[cfg(test)]
+    pub async fn set_history_expired(&self, key: String, sealing_key: &HistoryKey, data: Vec<HistoryEntry>) {
+        self.insert_history(key, sealing_key, data, Duration::ZERO, Duration::ZERO, HISTORY_MAX_STALENESS).await;
+    }
+
+    #[cfg(test)]
+    async fn set_history_too_old(&self, key: String, sealing_key: &HistoryKey, data: Vec<HistoryEntry>) {
+        self.insert_history(key, sealing_key, data, Duration::ZERO, Duration::ZERO, Duration::ZERO).await;
+    }
+}
+
+enum Flight {
+    Join(watch::Receiver<Option<FetchResult>>),
+    Lead(watch::Sender<Option<FetchResult>>),
+}
+
+struct RunningFetch<'a> {
+    fetches: &'a Mutex<HashMap<String, watch::Receiver<Option<FetchResult>>>>,
+    key: &'a str,
+}
+
+impl Drop for RunningFetch<'_> {
+    fn drop(&mut self) {
+        self.fetches.lock().unwrap().remove(self.key);
+    }
+}
+
+/// What a history fetch needs to know about a series or movie listing.
+#[derive(Debug, Clone, Serialize)]
+pub struct MediaMetadata {
+    pub title: String,
+    pub genres: Vec<Genre>,
+    /// Posters, used for entries whose panel has no thumbnails.
+    pub images: Vec<Image>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MetadataCacheStats {
+    /// Series and movie listings.
+    pub entries: usize,
+    pub catalogues: usize,
+    pub bytes: usize,
+    pub evictions: u64,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+/// Entries of one kind in the `MetadataCache`, each kept for the same TTL.
+struct TtlMap<K, V> {
+    entries: tokio::sync::Mutex<Lru<K, CacheEntry<V>>>,
+    limits: CacheLimits,
+}
+
+impl<K: Clone + Eq + Hash, V: Clone + Serialize> TtlMap<K, V> {
+    fn new(limits: CacheLimits) -> Self {
+        Self {
+            entries: tokio::sync::Mutex::new(Lru::new()),
+            limits,
+        }
+    }
+
+    async fn get(&self, key: &K) -> Option<V> {
+        let mut entries = self.entries.lock().await;
+        entries
+            .get(key)
+            .filter(|entry| !entry.is_expired())
+            .map(|entry| entry.data.clone())
+    }
+
+    /// Evicts the least recently used entries past `limits`, returning how
+    /// many. Sizes are measured as JSON, like histories.
+    async fn insert(&self, key: K, data: V, ttl: Duration) -> usize {
+        let bytes = serde_json::to_vec(&data).map_or(0, |json| json.len());
+        let mut entries = self.entries.lock().await;
+        entries.insert(key, CacheEntry {
+            data,
+            inserted_at: Instant::now(),
+            ttl,
+            max_age: ttl,
+        }, bytes);
+        entries.evict_past(self.limits)
+    }
+
+    /// How many entries there are and their size.
+    async fn size(&self) -> (usize, usize) {
+        let entries = self.entries.lock().await;
+        (entries.len(), entries.bytes())
+    }
+
+    async fn sweep(&self) {
+        self.entries.lock().await.retain(|_, entry| !entry.is_expired());
+    }
+
+    async fn clear(&self) {
+        self.entries.lock().await.clear();
+    }
+}
+
+/// Series and movie listing metadata, and series catalogues, shared across
+/// users so a show many people watch is looked up once per `METADATA_TTL`
+/// rather than once per user. Episodes are keyed by their series and movies
+/// by their listing. Each kind is bounded by `CacheLimits` and expired
+/// entries are swept every `METADATA_SWEEP_INTERVAL`.
+pub struct MetadataCache {
+    media: TtlMap<(MediaKind, String), MediaMetadata>,
+    catalogues: TtlMap<String, SeriesCatalogue>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+}
+
+impl MetadataCache {
+    pub fn new(limits: CacheLimits) -> Arc<Self> {
+        let cache = Arc::new(Self {
+            media: TtlMap::new(limits),
+            catalogues: TtlMap::new(limits),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+        });
+
+        // Periodic removal of expired metadata
+        let cache_clone = cache.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(METADATA_SWEEP_INTERVAL).await;
+                cache_clone.sweep().await;
+            }
+        });
+
+        cache
+    }
+
+    /// Counts a hit or a miss; expired entries are misses.
+    pub async fn get(&self, kind: MediaKind, id: &str) -> Option<MediaMetadata> {
+        self.count(self.media.get(&(kind, id.to_string())).await)
+    }
+
+    pub async fn set(&self, kind: MediaKind, id: String, data: MediaMetadata) {
+        let evicted = self.media.insert((kind, id), data, METADATA_TTL).await;
+        self.evictions.fetch_add(evicted as u64, Ordering::Relaxed);
+    }
+
+    /// Counts a hit or a miss like `get`.
+    pub async fn get_catalogue(&self, series_id: &str) -> Option<SeriesCatalogue> {
+        self.count(self.catalogues.get(&series_id.to_string()).await)
+    }
+
+    pub async fn set_catalogue(&self, catalogue: SeriesCatalogue) {
+        let evicted = self.catalogues.insert(catalogue.series_id.clone(), catalogue, METADATA_TTL).await;
+        self.evictions.fetch_add(evicted as u64, Ordering::Relaxed);
+    }
+
+    pub async fn stats(&self) -> MetadataCacheStats {
+        let (entries, media_bytes) = self.media.size().await;
+        let (catalogues, catalogue_bytes) = self.catalogues.size().await;
+        MetadataCacheStats {
+            entries,
+            catalogues,
+            bytes: media_bytes + catalogue_bytes,
+            evictions: self.evictions.load(Ordering::Relaxed),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+
+    pub async fn clear(&self) {
+        self.media.clear().await;
+        self.catalogues.clear().await;
+    }
+
+    async fn sweep(&self) {
+        self.media.sweep().await;
+        self.catalogues.sweep().await;
+    }
+
+    fn count<T>(&self, found: Option<T>) -> Option<T> {
+        let counter = if found.is_some() { &self.hits } else { &self.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+        found
+    }
+
+    #[cfg(test)]
+    async fn set_expired(&self, kind: MediaKind, id: String, data: MediaMetadata) {
+        self.media.insert((kind, id), data, Duration::ZERO).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::MediaKind;
+
+    #[test]
+    fn cache_key_is_deterministic() {
+        let key1 = AppCache::cache_key("user@example.com");
+        let key2 = AppCache::cache_key("user@example.com");
+        assert_eq!(key1, key2);
+    }
+
+    #[test]
+    fn cache_key_different_emails_differ() {
+        let key1 = AppCache::cache_key("alice@example.com");
+        let key2 = AppCache::cache_key("bob@example.com");
+        assert_ne!(key1, key2);
+    }
+
+    #[test]
+    fn cache_key_is_64_char_hex() {
+        let key = AppCache::cache_key("user@example.com");
+        assert_eq!(key.len(), 64);
+        assert!(key.chars().all(|c| c.is_ascii_hexdigit()));
+    }
+
+    #[test]
+    fn history_key_differs_per_window() {
+        use crate::history::Since;
+
+        let user_key = AppCache::cache_key("user@example.com");
+        let all = HistoryWindow { since: Since::All, until: None };
+        assert_ne!(
+            AppCache::history_key(&user_key, &HistoryWindow::default()),
+            AppCache::history_key(&user_key, &all)
+        );
+        assert!(AppCache::history_key(&user_key, &all).starts_with(&user_key));
+    }
+
+    #[tokio::test]
+    async fn get_history_miss_returns_none() {
+        let cache = AppCache::new();
+        let result = cache.get_history("nonexistent", &HistoryKey::test()).await;
+        assert!(result.is_none());
+    }
+
+    #[tokio::test]
+    async fn set_and_get_history_returns_data() {
+        let cache = AppCache::new();
+        let data = vec![HistoryEntry::test("item-0", None), HistoryEntry::test("item-1", None)];
+        cache.set_history("key1".to_string(), &HistoryKey::test(), data).await;
+
+        let result = cache.get_history("key1", &HistoryKey::test()).await;
+        assert!(result.is_some());
+        let entries = result.unwrap().data;
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].id, "item-0");
+        assert_eq!(entries[1].id, "item-1");
+    }
+
+    #[tokio::test]
+    async fn fresh_history_is_not_stale() {
+        let cache = AppCache::new();
+        cache.set_history("key1".to_string(), &HistoryKey::test(), vec![HistoryEntry::test("item-0", None)]).await;
+
+        let cached = cache.get_history("key1", &HistoryKey::test()).await.unwrap();
+        assert!(!cached.stale);
+        assert!(cached.age < HISTORY_TTL);
+    }
+
+    #[tokio::test]
+    async fn expired_history_is_served_stale() {
+        let cache = AppCache::new();
+        cache.set_history_expired("key2".to_string(), &HistoryKey::test(), vec![HistoryEntry::test("item-0", None)]).await;
+
+        let cached = cache.get_history("key2", &HistoryKey::test()).await.unwrap();
+        assert!(cached.stale);
+        assert_eq!(cached.data[0].id, "item-0");
+    }
+
+    #[tokio::test]
+    async fn history_past_max_staleness_is_dropped() {
+        let cache = AppCache::new();
+        cache.set_history_too_old("key2".to_string(), &HistoryKey::test(), vec![HistoryEntry::test("item-0", None)]).await;
+
+        assert!(cache.get_history("key2", &HistoryKey::test()).await.is_none());
+        let stats = cache.history_stats().await.unwrap();
+        assert_eq!((stats.entries, stats.bytes, stats.expirations), (0, 0, 1));
+    }
+
+    fn make_metadata(title: &str) -> MediaMetadata {
+        MediaMetadata {
+            title: title.to_string(),
+            genres: vec![Genre::Fantasy],
+            images: vec![],
+        }
+    }
+
+    #[tokio::test]
+    async fn metadata_is_shared_and_counts_hits_and_misses() {
+        let cache = AppCache::new();
+        assert!(cache.metadata().get(MediaKind::Episode, "series-1").await.is_none());
+
+        cache.metadata().set(MediaKind::Episode, "series-1".to_string(), make_metadata("Frieren")).await;
+
+        let metadata = cache.metadata().get(MediaKind::Episode, "series-1").await.unwrap();
+        assert_eq!(metadata.title, "Frieren");
+        assert!(cache.metadata().get(MediaKind::Movie, "series-1").await.is_none());
+        let stats = cache.metadata().stats().await;
+        assert_eq!((stats.entries, stats.catalogues, stats.hits, stats.misses), (1, 0, 1, 2));
+    }
+
+    #[tokio::test]
+    async fn expired_metadata_is_a_miss() {
+        let cache = MetadataCache::new(CacheLimits::default());
+        cache.set_expired(MediaKind::Episode, "series-1".to_string(), make_metadata("Frieren")).await;
+
+        assert!(cache.get(MediaKind::Episode, "series-1").await.is_none());
+        assert_eq!(cache.stats().await.misses, 1);
+    }
+
+    #[tokio::test]
+    async fn catalogues_are_cached_by_series() {
+        let cache = MetadataCache::new(CacheLimits::default());
+        let catalogue = SeriesCatalogue {
+            series_id: "series-1".to_string(),
+            seasons: vec![],
+        };
+        assert!(cache.get_catalogue("series-1").await.is_none());
+
+        cache.set_catalogue(catalogue.clone()).await;
+
+        assert_eq!(cache.get_catalogue("series-1").await, Some(catalogue));
+        let stats = cache.stats().await;
+        assert_eq!((stats.entries, stats.catalogues, stats.hits, stats.misses), (0, 1, 1, 1));
+    }
+
+    #[tokio::test]
+    async fn sweep_drops_expired_metadata() {
+        let cache = MetadataCache::new(CacheLimits::default());
+        cache.set_expired(MediaKind::Episode, "series-1".to_string(), make_metadata("Frieren")).await;
+        cache.set(MediaKind::Episode, "series-2".to_string(), make_metadata("Dandadan")).await;
+
+        cache.sweep().await;
+
+        assert_eq!(cache.stats().await.entries, 1);
+        assert!(cache.get(MediaKind::Episode, "series-2").await.is_some());
+    }
+
+    #[tokio::test]
+    async fn least_recently_used_metadata_is_evicted_past_the_limits() {
+        let cache = MetadataCache::new(CacheLimits {
+            max_entries: 2,
+            ..CacheLimits::default()
+        });
+        cache.set(MediaKind::Episode, "series-1".to_string(), make_metadata("Frieren")).await;
+        cache.set(MediaKind::Episode, "series-2".to_string(), make_metadata("Dandadan")).await;
+        cache.get(MediaKind::Episode, "series-1").await.unwrap();
+
+        cache.set(MediaKind::Episode, "series-3".to_string(), make_metadata("Mushishi")).await;
+
+        assert!(cache.get(MediaKind::Episode, "series-1").await.is_some());
+        assert!(cache.get(MediaKind::Episode, "series-2").await.is_none());
+        let stats = cache.stats().await;
+        assert_eq!((stats.entries, stats.evictions), (2, 1));
+        assert!(stats.bytes > 0);
+    }
+
+    async fn slow_fetch(
+        fetches: &AtomicU64,
+        result: anyhow::Result<Vec<HistoryEntry>>,
+    ) -> anyhow::Result<Vec<HistoryEntry>> {
+        fetches.fetch_add(1, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        result
+    }
+
+    #[tokio::test]
+    async fn concurrent_fetches_for_a_key_run_once() {
+        let cache = AppCache::new();
+        let fetches = AtomicU64::new(0);
+
+        let (first, second, other) = tokio::join!(
+            cache.single_flight("key1", slow_fetch(&fetches, Ok(vec![HistoryEntry::test("item-0", None)]))),
+            cache.single_flight("key1", slow_fetch(&fetches, Ok(vec![]))),
+            cache.single_flight("key2", slow_fetch(&fetches, Ok(vec![]))),
+        );
+
+        assert_eq!(fetches.load(Ordering::SeqCst), 2);
+        assert_eq!(first.unwrap()[0].id, "item-0");
+        assert_eq!(second.unwrap()[0].id, "item-0");
+        assert!(other.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn waiters_share_the_error() {
+        let cache = AppCache::new();
+        let fetches = AtomicU64::new(0);
+
+        let (first, second) = tokio::join!(
+            cache.single_flight("key1", slow_fetch(&fetches, Err(anyhow!("upstream down")))),
+            cache.single_flight("key1", slow_fetch(&fetches, Ok(vec![]))),
+        );
+
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+        assert_eq!(first.unwrap_err().to_string(), "upstream down");
+        assert_eq!(second.unwrap_err().to_string(), "upstream down");
+    }
+
+    #[tokio::test]
+    async fn finished_fetches_are_not_reused() {
+        let cache = AppCache::new();
+        let fetches = AtomicU64::new(0);
+
+        cache.single_flight("key1", slow_fetch(&fetches, Ok(vec![]))).await.unwrap();
+        cache.single_flight("key1", slow_fetch(&fetches, Ok(vec![]))).await.unwrap();
+
+        assert_eq!(fetches.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn waiter_takes_over_a_dropped_fetch() {
+        let cache = AppCache::new();
+        let fetches = AtomicU64::new(0);
+
+        let abandoned = tokio::time::timeout(
+            Duration::from_millis(5),
+            cache.single_flight("key1", slow_fetch(&fetches, Ok(vec![]))),
+        );
+        let waiter = async {
+            tokio::task::yield_now().await;
+            cache.single_flight("key1", slow_fetch(&fetches, Ok(vec![HistoryEntry::test("item-1", None)]))).await
+        };
+        let (abandoned, waiter) = tokio::join!(abandoned, waiter);
+
+        assert!(abandoned.is_err());
+        assert_eq!(waiter.unwrap()[0].id, "item-1");
+        assert_eq!(fetches.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn history_lookups_count_hits_and_misses() {
+        let cache = AppCache::new();
+        cache.set_history("key1".to_string(), &HistoryKey::test(), vec![HistoryEntry::test("item-0", None)]).await;
+        cache.set_history_too_old("key2".to_string(), &HistoryKey::test(), vec![HistoryEntry::test("item-1", None)]).await;
+
+        cache.get_history("key1", &HistoryKey::test()).await.unwrap();
+        cache.get_history("key2", &HistoryKey::test()).await;
+        cache.get_history("missing", &HistoryKey::test()).await;
+
+        let stats = cache.history_stats().await.unwrap();
+        assert_eq!((stats.hits, stats.misses), (1, 2));
+    }
+
+    #[tokio::test]
+    async fn history_entries_are_listed_oldest_first() {
+        let cache = AppCache::new();
+        cache.set_history("key1".to_string(), &HistoryKey::test(), vec![HistoryEntry::test("item-0", None), HistoryEntry::test("item-1", None)]).await;
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        cache.set_history_expired("key2".to_string(), &HistoryKey::test(), vec![HistoryEntry::test("item-2", None)]).await;
+
+        let entries = cache.history_entries().await.unwrap();
+
+        let summary: Vec<_> = entries.iter().map(|e| (e.key.as_str(), e.items, e.stale)).collect();
+        assert_eq!(summary, vec![("key1", 2, false), ("key2", 1, true)]);
+        assert!(entries[0].age > entries[1].age);
+        assert_eq!(
+            entries.iter().map(|e| e.bytes).sum::<usize>(),
+            cache.history_stats().await.unwrap().bytes
+        );
+    }
+
+    #[tokio::test]
+    async fn invalidate_user_drops_only_their_windows() {
+        let cache = AppCache::new();
+        let alice = AppCache::cache_key("alice@example.com");
+        let bob = AppCache::cache_key("bob@example.com");
+        let all = HistoryWindow { since: crate::history::Since::All, until: None };
+        for key in [
+            AppCache::history_key(&alice, &HistoryWindow::default()),
+            AppCache::history_key(&alice, &all),
+            AppCache::history_key(&bob, &all),
+        ] {
+            cache.set_history(key, &HistoryKey::test(), vec![HistoryEntry::test("item-0", None)]).await;
+        }
+
+        assert_eq!(cache.invalidate_user(&alice).await.unwrap(), 2);
+        assert_eq!(cache.invalidate_user(&alice).await.unwrap(), 0);
+
+        let keys: Vec<_> = cache.history_entries().await.unwrap().into_iter().map(|e| e.key).collect();
+        assert_eq!(keys, vec![AppCache::history_key(&bob, &all)]);
+    }
+
+    #[tokio::test]
+    async fn flush_drops_histories_and_metadata() {
+        let cache = AppCache::new();
+        cache.set_history("key1".to_string(), &HistoryKey::test(), vec![HistoryEntry::test("item-0", None)]).await;
+        cache.metadata().set(MediaKind::Episode, "series-1".to_string(), make_metadata("Frieren")).await;
+
+        assert_eq!(cache.flush().await.unwrap(), 1);
+
+        let stats = cache.history_stats().await.unwrap();
+        assert_eq!((stats.entries, stats.bytes), (0, 0));
+        assert_eq!(cache.metadata().stats().await.entries, 0);
+    }
+}
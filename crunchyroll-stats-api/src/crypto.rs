@@ -0,0 +1,108 @@
+use anyhow::{anyhow, Result};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use zeroize::{Zeroize, ZeroizeOnDrop};
+
+const NONCE_LEN: usize = 24;
+const SALT_CONTEXT: &str = "crunchyroll-stats-api history key v1";
+
+/// Per-user key for history at rest, derived from the account password at
+/// login and only ever held in memory by that user's live sessions. Once
+/// they have all expired, the user's stored history cannot be read until
+/// they log in again.
+#[derive(Clone, Zeroize, ZeroizeOnDrop)]
+pub struct HistoryKey([u8; 32]);
+
+impl HistoryKey {
+    /// Argon2id over the password, salted with the user key so equal
+    /// passwords still give different keys per account. This is
+    /// deliberately slow; only call it after a successful login.
+    pub fn derive(password: &str, user_key: &str) -> Result<Self> {
+        let salt = format!("{}:{}", SALT_CONTEXT, user_key);
+        let mut key = [0u8; 32];
+        argon2::Argon2::default()
+            .hash_password_into(password.as_bytes(), salt.as_bytes(), &mut key)
+            .map_err(|error| anyhow!("failed to derive history key: {}", error))?;
+        Ok(Self(key))
+    }
+
+    #[cfg(test)]
+    pub fn from_bytes(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+
+    /// A fixed key, for tests that need one but not any one in particular.
+    #[cfg(test)]
+    pub fn test() -> Self {
+        Self::from_bytes([7; 32])
+    }
+
+    /// Encrypts `plaintext` bound to `user_key`, so a blob copied to another
+    /// user's row fails to open. Returns the nonce followed by the ciphertext.
+    pub fn seal(&self, user_key: &str, plaintext: &[u8]) -> Result<Vec<u8>> {
+        let nonce = rand::random::<[u8; NONCE_LEN]>();
+        let payload = Payload {
+            msg: plaintext,
+            aad: user_key.as_bytes(),
+        };
+        let ciphertext = self
+            .cipher()
+            .encrypt(&XNonce::from(nonce), payload)
+            .map_err(|_| anyhow!("failed to encrypt history"))?;
+        Ok([nonce.as_slice(), &ciphertext].concat())
+    }
+
+    /// Reverses `seal`. Fails for another key, another user or tampered data.
+    pub fn open(&self, user_key: &str, sealed: &[u8]) -> Result<Vec<u8>> {
+        if sealed.len() < NONCE_LEN {
+            return Err(anyhow!("sealed history is truncated"));
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        let nonce: [u8; NONCE_LEN] = nonce.try_into()?;
+        let payload = Payload {
+            msg: ciphertext,
+            aad: user_key.as_bytes(),
+        };
+        self.cipher()
+            .decrypt(&XNonce::from(nonce), payload)
+            .map_err(|_| anyhow!("failed to decrypt history"))
+    }
+
+    fn cipher(&self) -> XChaCha20Poly1305 {
+        XChaCha20Poly1305::new_from_slice(&self.0).expect("history keys are 32 bytes")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn derive_is_deterministic_per_password_and_user() {
+        let key = HistoryKey::derive("hunter22", "alice").unwrap();
+        assert_eq!(key.0, HistoryKey::derive("hunter22", "alice").unwrap().0);
+        assert_ne!(key.0, HistoryKey::derive("hunter23", "alice").unwrap().0);
+        assert_ne!(key.0, HistoryKey::derive("hunter22", "bob").unwrap().0);
+    }
+
+    #[test]
+    fn seal_round_trips_and_hides_plaintext() {
+        let key = HistoryKey::from_bytes([7; 32]);
+        let sealed = key.seal("alice", b"Frieren").unwrap();
+
+        assert!(!sealed.windows(7).any(|window| window == b"Frieren"));
+        assert_eq!(key.open("alice", &sealed).unwrap(), b"Frieren");
+    }
+
+    #[test]
+    fn open_fails_for_wrong_key_user_or_tampering() {
+        let key = HistoryKey::from_bytes([7; 32]);
+        let mut sealed = key.seal("alice", b"Frieren").unwrap();
+
+        assert!(HistoryKey::from_bytes([8; 32]).open("alice", &sealed).is_err());
+        assert!(key.open("bob", &sealed).is_err());
+        assert!(key.open("alice", &sealed[..10]).is_err());
+        *sealed.last_mut().unwrap() ^= 1;
+        assert!(key.open("alice", &sealed).is_err());
+    }
+}
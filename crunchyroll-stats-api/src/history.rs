@@ -1,208 +1,1168 @@
-use crate::{auth::CrunchyrollClient, models::{HistoryEntry, Image}};
+use crate::cache::{MediaMetadata, MetadataCache};
+use crate::{models::{EpisodeMetadata, Genre, HistoryEntry, Image, MediaKind, WatchStatus}, source::WatchDataSource};
 use anyhow::Result;
-use chrono::{Duration, Utc};
-use futures_util::StreamExt;
-use std::collections::HashMap;
+use chrono::{DateTime, Duration, Utc};
+use crunchyroll_rs::list::WatchHistoryEntry;
+use crunchyroll_rs::media::PosterImages;
+use crunchyroll_rs::{Episode, MediaCollection, MovieListing, Series};
+use futures_util::stream::{self, StreamExt, TryStreamExt};
+use futures_util::FutureExt;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::future;
+use tokio::sync::mpsc::UnboundedSender;
 
-pub struct History<'a> {
-    client: &'a CrunchyrollClient,
+const DEFAULT_WINDOW_DAYS: i64 = 365;
+const DEFAULT_PAGE_SIZE: usize = 20; // crunchyroll-rs' page size
+pub const DEFAULT_COMPLETION_THRESHOLD: f64 = 0.9;
+const SAMPLE_LIMIT: Duration = Duration::minutes(2); // Shorter plays are only a peek
+
+/// How far back the history goes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Since {
+    /// The whole history.
+    All,
+    /// A window relative to the time of the fetch.
+    Days(i64),
+    At(DateTime<Utc>),
+}
+
+/// The slice of the watch history to fetch. `Since::Days` is kept relative
+/// rather than resolved up front so the same window always maps to the same
+/// cache key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HistoryWindow {
+    pub since: Since,
+    /// Entries played after this are skipped. `None` means up to now.
+    pub until: Option<DateTime<Utc>>,
 }
 
-impl<'a> History<'a> {
-    pub fn new(client: &'a CrunchyrollClient) -> Self {
-        Self { client }
+impl Default for HistoryWindow {
+    fn default() -> Self {
+        Self {
+            since: Since::Days(DEFAULT_WINDOW_DAYS),
+            until: None,
+        }
     }
+}
 
-    pub async fn fetch_history(&self, limit: Option<usize>) -> Result<Vec<HistoryEntry>> {
-        let mut history = Vec::new();
-        let mut series_genres_cache: HashMap<String, Vec<String>> = HashMap::new();
-        let mut movie_listing_genres_cache: HashMap<String, Vec<String>> = HashMap::new();
-        let mut pagination = self.client.client.watch_history();
+impl HistoryWindow {
+    /// Oldest play time inside the window, `None` for the whole history.
+    pub fn cutoff(&self) -> Option<DateTime<Utc>> {
+        match self.since {
+            Since::All => None,
+            Since::Days(days) => Some(Utc::now() - Duration::days(days)),
+            Since::At(since) => Some(since),
+        }
+    }
+
+    pub fn contains(&self, played: DateTime<Utc>) -> bool {
+        self.cutoff().is_none_or(|cutoff| played >= cutoff)
+            && self.until.is_none_or(|until| played <= until)
+    }
+}
+
+/// Stable textual form, used as part of the history cache key.
+impl fmt::Display for HistoryWindow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.since {
+            Since::All => write!(f, "since=all")?,
+            Since::Days(days) => write!(f, "since={}d", days)?,
+            Since::At(since) => write!(f, "since={}", since.timestamp())?,
+        }
+        match self.until {
+            Some(until) => write!(f, ",until={}", until.timestamp()),
+            None => write!(f, ",until=now"),
+        }
+    }
+}
+
+/// Identifies one play of one piece of content, so the same entry keeps
+/// its id across refreshes while a later replay gets a new one.
+pub fn stable_id(content_id: &str, played: DateTime<Utc>) -> String {
+    format!("{}-{}", content_id, played.timestamp())
+}
+
+/// Fills in `watched_ms` and `watch_status` from the entry's playhead and
+/// duration. A play is completed once it reaches `completion_threshold` of
+/// the duration, and sampled if it stopped short of that within the first
+/// couple of minutes. Without a duration nothing counts as completed.
+pub fn classify(entry: &mut HistoryEntry, completion_threshold: f64) {
+    let playhead_ms = entry.playhead.map_or(0, |playhead| u64::from(playhead) * 1000);
+    entry.watched_ms = match entry.duration_ms {
+        Some(duration_ms) => playhead_ms.min(duration_ms),
+        None => playhead_ms,
+    };
+
+    let completed = entry
+        .duration_ms
+        .filter(|duration_ms| *duration_ms > 0)
+        .is_some_and(|duration_ms| entry.watched_ms as f64 >= duration_ms as f64 * completion_threshold);
+    entry.watch_status = if completed {
+        WatchStatus::Completed
+    } else if entry.watched_ms < SAMPLE_LIMIT.num_milliseconds() as u64 {
+        WatchStatus::Sampled
+    } else {
+        WatchStatus::Partial
+    };
+}
+
+fn episode_metadata(episode: &Episode) -> EpisodeMetadata {
+    EpisodeMetadata {
+        season_number: episode.season_number,
+        season_title: episode.season_title.clone(),
+        episode_number: episode.episode_number,
+        sequence_number: episode.sequence_number,
+        audio_locale: episode.audio_locale.to_string(),
+        subtitle_locales: episode.subtitle_locales.iter().map(ToString::to_string).collect(),
+        is_dubbed: episode.is_dubbed,
+        is_subbed: episode.is_subbed,
+        maturity_ratings: episode.maturity_ratings.clone(),
+        // Unset dates deserialize to the Unix epoch.
+        air_date: Some(episode.episode_air_date).filter(|air_date| air_date.timestamp() > 0),
+        is_premium_only: episode.is_premium_only,
+    }
+}
+
+fn series_metadata(series: Series) -> MediaMetadata {
+    MediaMetadata {
+        title: series.title,
+        genres: series.categories.unwrap_or_default().iter().map(Genre::from).collect(),
+        images: poster_images(&series.images),
+    }
+}
+
+fn movie_listing_metadata(listing: MovieListing) -> MediaMetadata {
+    MediaMetadata {
+        title: listing.title,
+        genres: listing.categories.unwrap_or_default().iter().map(Genre::from).collect(),
+        images: poster_images(&listing.images),
+    }
+}
+
+fn poster_images(images: &PosterImages) -> Vec<Image> {
+    images
+        .poster_tall
+        .iter()
+        .map(|img| Image { source: img.source.clone(), width: img.width })
+        .collect()
+}
+
+/// A series' seasons and their episodes as Crunchyroll lists them.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SeriesCatalogue {
+    pub series_id: String,
+    pub seasons: Vec<SeasonListing>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SeasonListing {
+    pub id: String,
+    pub season_number: u32,
+    pub title: String,
+    /// One entry per episode: its id followed by the ids of its other
+    /// audio versions, any of which may show up in the history.
+    pub episodes: Vec<Vec<String>>,
+}
 
-        if let Some(limit) = limit {
-            pagination.page_size(limit as u32);
+pub struct History<'a, S: WatchDataSource> {
+    source: &'a S,
+    session: &'a S::Session,
+    metadata_cache: Option<&'a MetadataCache>,
+    pages: Option<UnboundedSender<Vec<HistoryEntry>>>,
+}
+
+impl<'a, S: WatchDataSource> History<'a, S> {
+    pub fn new(source: &'a S, session: &'a S::Session) -> Self {
+        Self {
+            source,
+            session,
+            metadata_cache: None,
+            pages: None,
         }
+    }
+
+    /// Reads series and movie listing metadata through `cache`, which is
+    /// shared with every other user's fetches.
+    pub fn with_metadata_cache(mut self, cache: &'a MetadataCache) -> Self {
+        self.metadata_cache = Some(cache);
+        self
+    }
 
-        let cutoff = Utc::now() - Duration::days(365);
-        let mut index = 0usize;
-        while let Some(entry) = pagination.next().await {
-            let entry = entry?;
+    /// Sends the entries of each page to `pages` as soon as it is resolved,
+    /// ahead of the full result.
+    pub fn with_pages(mut self, pages: UnboundedSender<Vec<HistoryEntry>>) -> Self {
+        self.pages = Some(pages);
+        self
+    }
+
+    pub async fn fetch_history(
+        &self,
+        limit: Option<usize>,
+        window: &HistoryWindow,
+    ) -> Result<Vec<HistoryEntry>> {
+        let cutoff = window.cutoff();
+        self.fetch_entries(limit, window.until, |played| {
+            cutoff.is_some_and(|cutoff| played < cutoff)
+        })
+        .await
+    }
 
-            if entry.date_played < cutoff {
-                break;
+    /// Entries played after `after`, stopping at the first one that is not.
+    /// With `None` the whole history is fetched.
+    pub async fn fetch_newer_than(
+        &self,
+        limit: Option<usize>,
+        after: Option<DateTime<Utc>>,
+    ) -> Result<Vec<HistoryEntry>> {
+        self.fetch_entries(limit, None, |played| {
+            after.is_some_and(|after| played <= after)
+        })
+        .await
+    }
+
+    /// Season and episode lists for every distinct series in `entries`,
+    /// in order of first appearance. Each series is looked up once, up to
+    /// `metadata_concurrency` at a time and through the shared metadata
+    /// cache first; series whose lists cannot be fetched are left out rather
+    /// than reported as partially available.
+    pub async fn fetch_catalogues(&self, entries: &[HistoryEntry]) -> Vec<SeriesCatalogue> {
+        let mut seen = HashSet::new();
+        let series_ids: Vec<&str> = entries
+            .iter()
+            .filter_map(|entry| entry.series_id.as_deref())
+            .filter(|series_id| seen.insert(*series_id))
+            .collect();
+        stream::iter(series_ids)
+            .map(|series_id| self.lookup_catalogue(series_id))
+            .buffered(self.source.metadata_concurrency().max(1))
+            .filter_map(future::ready)
+            .collect()
+            .await
+    }
+
+    /// Failed lookups are logged and not cached, like `lookup_metadata`.
+    async fn lookup_catalogue(&self, series_id: &str) -> Option<SeriesCatalogue> {
+        if let Some(cache) = self.metadata_cache {
+            if let Some(catalogue) = cache.get_catalogue(series_id).await {
+                return Some(catalogue);
             }
+        }
 
-            let playhead = entry.playhead;
-            let watched_at = Some(entry.date_played.to_rfc3339());
+        match self.fetch_catalogue(series_id).await {
+            Ok(catalogue) => {
+                if let Some(cache) = self.metadata_cache {
+                    cache.set_catalogue(catalogue.clone()).await;
+                }
+                Some(catalogue)
+            }
+            Err(error) => {
+                tracing::warn!("Failed to fetch seasons for series {}: {}", series_id, error);
+                None
+            }
+        }
+    }
 
-            let panel = match entry.panel {
-                Some(panel) => panel,
-                None => {
-                    let entry_id = entry.id.clone();
-                    let parent_id = entry.parent_id.clone();
-                    let parent_type = entry.parent_type.clone();
+    async fn fetch_catalogue(&self, series_id: &str) -> Result<SeriesCatalogue> {
+        let series = self.source.series(self.session, series_id).await?;
+        let mut seasons = Vec::new();
+        // Dubs of a season are listed as versions of it; only the first
+        // listed one is kept so its episodes are not counted once per dub.
+        let mut dub_ids = HashSet::new();
+        for season in self.source.seasons(self.session, &series).await? {
+            if dub_ids.contains(&season.id) {
+                continue;
+            }
+            dub_ids.extend(season.versions.iter().map(|version| version.id.clone()));
 
-                    let from_entry_id =
-                        self.client.client.media_collection_from_id(&entry_id).await;
-                    if let Ok(panel) = from_entry_id {
-                        panel
-                    } else {
-                        let from_parent_id = if parent_id != entry_id {
-                            Some(
-                                self.client
-                                    .client
-                                    .media_collection_from_id(&parent_id)
-                                    .await,
-                            )
-                        } else {
-                            None
-                        };
-
-                        match from_parent_id {
-                            Some(Ok(panel)) => panel,
-                            Some(Err(parent_error)) => {
-                                let entry_error = from_entry_id
-                                    .err()
-                                    .map(|error| error.to_string())
-                                    .unwrap_or_else(|| "unknown error".to_string());
-                                tracing::warn!(
-                                    "Watch history entry {} had no panel and could not be resolved (parent_type={}, parent_id={}). entry lookup error: {}. parent lookup error: {}",
-                                    entry_id,
-                                    parent_type,
-                                    parent_id,
-                                    entry_error,
-                                    parent_error
-                                );
-                                continue;
-                            }
-                            None => {
-                                let entry_error = from_entry_id
-                                    .err()
-                                    .map(|error| error.to_string())
-                                    .unwrap_or_else(|| "unknown error".to_string());
-                                tracing::warn!(
-                                    "Watch history entry {} had no panel and could not be resolved (parent_type={}, parent_id={}). entry lookup error: {}. parent lookup skipped because parent id matched entry id.",
-                                    entry_id,
-                                    parent_type,
-                                    parent_id,
-                                    entry_error
-                                );
-                                continue;
-                            }
+            let episodes = self
+                .source
+                .episodes(self.session, &season)
+                .await?
+                .into_iter()
+                .map(|episode| {
+                    let mut ids = vec![episode.id];
+                    for version in episode.versions {
+                        if !ids.contains(&version.id) {
+                            ids.push(version.id);
                         }
                     }
-                }
-            };
+                    ids
+                })
+                .collect();
+            seasons.push(SeasonListing {
+                id: season.id,
+                season_number: season.season_number,
+                title: season.title,
+                episodes,
+            });
+        }
+        Ok(SeriesCatalogue {
+            series_id: series_id.to_string(),
+            seasons,
+        })
+    }
 
-            let history_entry = match panel {
-                crunchyroll_rs::MediaCollection::Episode(episode) => {
-                    let duration_ms = episode.duration.num_milliseconds() as u64;
-                    let series_id = episode.series_id.clone();
-                    let content_id = episode.id.clone();
+    /// Walks the history newest first until `stop` returns true for an
+    /// entry's play time, skipping entries played after `until`.
+    async fn fetch_entries(
+        &self,
+        limit: Option<usize>,
+        until: Option<DateTime<Utc>>,
+        stop: impl Fn(DateTime<Utc>) -> bool,
+    ) -> Result<Vec<HistoryEntry>> {
+        let concurrency = self.source.metadata_concurrency().max(1);
+        let page_size = limit.unwrap_or(DEFAULT_PAGE_SIZE).max(1);
 
-                    let images: Vec<Image> = episode
-                        .images
-                        .iter()
-                        .map(|img| Image { source: img.source.clone(), width: img.width })
-                        .collect();
+        // Pages are walked in order while panel lookups for entries that
+        // lack one run alongside; `try_buffered` hands them back in order.
+        let mut pages = self
+            .source
+            .watch_history(self.session, limit.map(|limit| limit as u32))
+            .try_take_while(|entry| future::ready(Ok(!stop(entry.date_played))))
+            .try_filter(|entry| future::ready(until.is_none_or(|until| entry.date_played <= until)))
+            .map_ok(|entry| self.resolve_play(entry).map(Ok))
+            .try_buffered(concurrency)
+            .chunks(page_size);
 
-                    let genres = if let Some(cached) = series_genres_cache.get(&series_id) {
-                        cached.clone()
-                    } else {
-                        let mut raw_categories = episode.categories.clone().unwrap_or_default();
-                        if raw_categories.is_empty() {
-                            match episode.series().await {
-                                Ok(series) => {
-                                    raw_categories = series.categories.unwrap_or_default();
-                                }
-                                Err(error) => {
-                                    tracing::warn!(
-                                        "Failed to fetch series metadata for {}: {}",
-                                        series_id,
-                                        error
-                                    );
-                                }
-                            }
-                        }
+        let mut history = Vec::new();
+        let mut metadata = MetadataMap::new();
+        let mut seen = HashSet::new();
+        while let Some(page) = pages.next().await {
+            let plays: Vec<Play> = page
+                .into_iter()
+                .collect::<Result<Vec<_>>>()?
+                .into_iter()
+                .flatten()
+                .collect();
+            self.fetch_metadata(&plays, concurrency, &mut metadata, &mut seen).await;
 
-                        let resolved: Vec<String> = raw_categories.into_iter().map(|c| c.to_string()).collect();
-                        series_genres_cache.insert(series_id.clone(), resolved.clone());
-                        resolved
-                    };
+            let entries: Vec<HistoryEntry> = plays
+                .into_iter()
+                .filter_map(|play| {
+                    let mut history_entry = history_entry(play, &metadata)?;
+                    classify(&mut history_entry, DEFAULT_COMPLETION_THRESHOLD);
+                    // Earlier plays are only known to the store, see `sync::annotate`.
+                    history_entry.first_watched_at = history_entry.watched_at.clone();
+                    Some(history_entry)
+                })
+                .collect();
+            if let Some(pages) = &self.pages {
+                // Nobody may be listening any more; the fetch carries on.
+                let _ = pages.send(entries.clone());
+            }
+            history.extend(entries);
+        }
 
-                    HistoryEntry {
-                        id: format!("item-{}", index),
-                        media_type: "episode".to_string(),
-                        content_id: Some(content_id),
-                        series_id: Some(series_id),
-                        movie_listing_id: None,
-                        title: episode.series_title,
-                        episode_title: Some(episode.title),
-                        watched_at,
-                        playhead: Some(playhead),
-                        duration_ms: Some(duration_ms),
-                        images,
-                        genres,
-                    }
-                }
-                crunchyroll_rs::MediaCollection::Movie(movie) => {
-                    let duration_ms = movie.duration.num_milliseconds() as u64;
-                    let content_id = movie.id.clone();
-                    let movie_listing_id = movie.movie_listing_id.clone();
-
-                    let images: Vec<Image> = movie
-                        .images
-                        .thumbnail
-                        .iter()
-                        .map(|img| Image { source: img.source.clone(), width: img.width })
-                        .collect();
-
-                    let genres = if let Some(cached) =
-                        movie_listing_genres_cache.get(&movie_listing_id)
-                    {
-                        cached.clone()
+        Ok(history)
+    }
+
+    /// The entry with its panel, looked up by the entry id and then the
+    /// parent id when the history did not include it. `None` if neither
+    /// lookup finds it.
+    async fn resolve_play(&self, entry: WatchHistoryEntry) -> Option<Play> {
+        let panel = match entry.panel {
+            Some(panel) => panel,
+            None => {
+                let entry_id = entry.id.clone();
+                let parent_id = entry.parent_id.clone();
+                let parent_type = entry.parent_type.clone();
+
+                let from_entry_id = self
+                    .source
+                    .media_collection_from_id(self.session, &entry_id)
+                    .await;
+                if let Ok(panel) = from_entry_id {
+                    panel
+                } else {
+                    let from_parent_id = if parent_id != entry_id {
+                        Some(
+                            self.source
+                                .media_collection_from_id(self.session, &parent_id)
+                                .await,
+                        )
                     } else {
-                        let genres = match movie.movie_listing().await {
-                            Ok(listing) => {
-                                let raw_categories = listing.categories.clone().unwrap_or_default();
-                                raw_categories.into_iter().map(|c| c.to_string()).collect()
-                            }
-                            Err(error) => {
-                                tracing::warn!(
-                                    "Failed to fetch movie listing {}: {}",
-                                    movie_listing_id,
-                                    error
-                                );
-                                Vec::new()
-                            }
-                        };
-
-                        movie_listing_genres_cache.insert(movie_listing_id.clone(), genres.clone());
-                        genres
+                        None
                     };
 
-                    HistoryEntry {
-                        id: format!("item-{}", index),
-                        media_type: "movie".to_string(),
-                        content_id: Some(content_id),
-                        series_id: None,
-                        movie_listing_id: Some(movie_listing_id),
-                        title: movie.title,
-                        episode_title: None,
-                        watched_at,
-                        playhead: Some(playhead),
-                        duration_ms: Some(duration_ms),
-                        images,
-                        genres,
+                    match from_parent_id {
+                        Some(Ok(panel)) => panel,
+                        Some(Err(parent_error)) => {
+                            let entry_error = from_entry_id
+                                .err()
+                                .map(|error| error.to_string())
+                                .unwrap_or_else(|| "unknown error".to_string());
+                            tracing::warn!(
+                                "Watch history entry {} had no panel and could not be resolved (parent_type={}, parent_id={}). entry lookup error: {}. parent lookup error: {}",
+                                entry_id,
+                                parent_type,
+                                parent_id,
+                                entry_error,
+                                parent_error
+                            );
+                            return None;
+                        }
+                        None => {
+                            let entry_error = from_entry_id
+                                .err()
+                                .map(|error| error.to_string())
+                                .unwrap_or_else(|| "unknown error".to_string());
+                            tracing::warn!(
+                                "Watch history entry {} had no panel and could not be resolved (parent_type={}, parent_id={}). entry lookup error: {}. parent lookup skipped because parent id matched entry id.",
+                                entry_id,
+                                parent_type,
+                                parent_id,
+                                entry_error
+                            );
+                            return None;
+                        }
                     }
                 }
+            }
+        };
+
+        Some(Play {
+            date_played: entry.date_played,
+            playhead: entry.playhead,
+            panel,
+        })
+    }
+
+    /// Adds metadata for the series and movie listings in `plays` that are
+    /// not in `seen` yet. A series takes the categories of its first episode
+    /// when that has any and is looked up otherwise; movie listings are
+    /// always looked up. Each id is looked up at most once per fetch, with
+    /// up to `concurrency` lookups at a time, and the shared metadata cache
+    /// is tried first.
+    async fn fetch_metadata(
+        &self,
+        plays: &[Play],
+        concurrency: usize,
+        metadata: &mut MetadataMap,
+        seen: &mut HashSet<(MediaKind, String)>,
+    ) {
+        let mut lookups = Vec::new();
+        for play in plays {
+            let (kind, id) = match &play.panel {
+                MediaCollection::Episode(episode) => (MediaKind::Episode, &episode.series_id),
+                MediaCollection::Movie(movie) => (MediaKind::Movie, &movie.movie_listing_id),
                 _ => continue,
             };
+            if !seen.insert((kind, id.clone())) {
+                continue;
+            }
+            match &play.panel {
+                MediaCollection::Episode(episode)
+                    if episode.categories.as_ref().is_some_and(|categories| !categories.is_empty()) =>
+                {
+                    let categories = episode.categories.as_deref().unwrap_or_default();
+                    metadata.insert((kind, id.clone()), MediaMetadata {
+                        title: episode.series_title.clone(),
+                        genres: categories.iter().map(Genre::from).collect(),
+                        images: Vec::new(),
+                    });
+                }
+                _ => lookups.push((kind, id.clone())),
+            }
+        }
+
+        let looked_up: Vec<_> = stream::iter(lookups)
+            .map(|(kind, id)| async move {
+                let found = self.lookup_metadata(kind, &id).await?;
+                Some(((kind, id), found))
+            })
+            .buffer_unordered(concurrency)
+            .filter_map(future::ready)
+            .collect()
+            .await;
+        metadata.extend(looked_up);
+    }
 
-            history.push(history_entry);
-            index += 1;
+    /// A series (for `MediaKind::Episode`) or movie listing. Failed lookups
+    /// are logged and not cached, so the next fetch tries again.
+    async fn lookup_metadata(&self, kind: MediaKind, id: &str) -> Option<MediaMetadata> {
+        if let Some(cache) = self.metadata_cache {
+            if let Some(metadata) = cache.get(kind, id).await {
+                return Some(metadata);
+            }
         }
 
-        Ok(history)
+        let metadata = match kind {
+            MediaKind::Episode => match self.source.series(self.session, id).await {
+                Ok(series) => series_metadata(series),
+                Err(error) => {
+                    tracing::warn!("Failed to fetch series metadata for {}: {}", id, error);
+                    return None;
+                }
+            },
+            MediaKind::Movie => match self.source.movie_listing(self.session, id).await {
+                Ok(listing) => movie_listing_metadata(listing),
+                Err(error) => {
+                    tracing::warn!("Failed to fetch movie listing {}: {}", id, error);
+                    return None;
+                }
+            },
+        };
+        if let Some(cache) = self.metadata_cache {
+            cache.set(kind, id.to_string(), metadata.clone()).await;
+        }
+        Some(metadata)
+    }
+}
+
+/// A history entry with its panel resolved.
+struct Play {
+    date_played: DateTime<Utc>,
+    playhead: u32,
+    panel: MediaCollection,
+}
+
+/// Keyed by series id for episodes and movie listing id for movies.
+type MetadataMap = HashMap<(MediaKind, String), MediaMetadata>;
+
+/// Maps `play` to an entry. The series or listing fills in genres, and the
+/// title and images when the panel came without them.
+fn history_entry(play: Play, metadata: &MetadataMap) -> Option<HistoryEntry> {
+    let Play {
+        date_played,
+        playhead,
+        panel,
+    } = play;
+    let watched_at = Some(date_played.to_rfc3339());
+
+    let history_entry = match panel {
+        MediaCollection::Episode(episode) => {
+            let duration_ms = episode.duration.num_milliseconds() as u64;
+            let series_id = episode.series_id.clone();
+            let content_id = episode.id.clone();
+
+            let images: Vec<Image> = episode
+                .images
+                .iter()
+                .map(|img| Image { source: img.source.clone(), width: img.width })
+                .collect();
+
+            let series = metadata.get(&(MediaKind::Episode, series_id.clone()));
+            let metadata = episode_metadata(&episode);
+            let (title, images, genres) = fill_in(episode.series_title, images, series);
+
+            HistoryEntry {
+                id: stable_id(&content_id, date_played),
+                media_type: MediaKind::Episode,
+                content_id: Some(content_id),
+                series_id: Some(series_id),
+                movie_listing_id: None,
+                title,
+                episode_title: Some(episode.title),
+                watched_at,
+                playhead: Some(playhead),
+                duration_ms: Some(duration_ms),
+                watched_ms: 0,
+                watch_status: WatchStatus::default(),
+                rewatch_count: 0,
+                first_watched_at: None,
+                episode_metadata: Some(metadata),
+                images,
+                genres,
+            }
+        }
+        MediaCollection::Movie(movie) => {
+            let duration_ms = movie.duration.num_milliseconds() as u64;
+            let content_id = movie.id.clone();
+            let movie_listing_id = movie.movie_listing_id.clone();
+
+            let images: Vec<Image> = movie
+                .images
+                .thumbnail
+                .iter()
+                .map(|img| Image { source: img.source.clone(), width: img.width })
+                .collect();
+
+            let listing = metadata.get(&(MediaKind::Movie, movie_listing_id.clone()));
+            let (title, images, genres) = fill_in(movie.title, images, listing);
+
+            HistoryEntry {
+                id: stable_id(&content_id, date_played),
+                media_type: MediaKind::Movie,
+                content_id: Some(content_id),
+                series_id: None,
+                movie_listing_id: Some(movie_listing_id),
+                title,
+                episode_title: None,
+                watched_at,
+                playhead: Some(playhead),
+                duration_ms: Some(duration_ms),
+                watched_ms: 0,
+                watch_status: WatchStatus::default(),
+                rewatch_count: 0,
+                first_watched_at: None,
+                episode_metadata: None,
+                images,
+                genres,
+            }
+        }
+        _ => return None,
+    };
+    Some(history_entry)
+}
+
+fn fill_in(
+    title: String,
+    images: Vec<Image>,
+    metadata: Option<&MediaMetadata>,
+) -> (String, Vec<Image>, Vec<Genre>) {
+    let Some(metadata) = metadata else {
+        return (title, images, Vec::new());
+    };
+    let title = if title.is_empty() { metadata.title.clone() } else { title };
+    let images = if images.is_empty() { metadata.images.clone() } else { images };
+    (title, images, metadata.genres.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::CacheLimits;
+    use crate::source::fixture::{self, FixtureSource};
+
+    const EMAIL: &str = "user@example.com";
+
+    async fn fetch(source: &FixtureSource) -> Vec<HistoryEntry> {
+        fetch_window(source, HistoryWindow::default()).await
+    }
+
+    async fn fetch_window(source: &FixtureSource, window: HistoryWindow) -> Vec<HistoryEntry> {
+        let session = EMAIL.to_string();
+        History::new(source, &session)
+            .fetch_history(Some(100), &window)
+            .await
+            .unwrap()
+    }
+
+    fn days_ago(days: i64) -> chrono::DateTime<Utc> {
+        Utc::now() - Duration::days(days)
+    }
+
+    /// One Frieren episode per age, newest first.
+    fn source_played_days_ago(ages: &[i64]) -> FixtureSource {
+        ages.iter().enumerate().fold(FixtureSource::new(), |source, (index, days)| {
+            let id = format!("ep-{}", index);
+            source.with_entry(fixture::played(
+                MediaCollection::Episode(fixture::episode(&id, "series-1", "Frieren", &id)),
+                days_ago(*days),
+                0,
+            ))
+        })
+    }
+
+    fn hours_ago(hours: i64) -> chrono::DateTime<Utc> {
+        Utc::now() - Duration::hours(hours)
+    }
+
+    #[tokio::test]
+    async fn maps_episodes_and_movies() {
+        let episode = fixture::episode("ep-1", "series-1", "Frieren", "The Journey's End");
+        let movie = fixture::movie("movie-1", "listing-1", "Suzume");
+        let source = FixtureSource::new()
+            .with_entry(fixture::played(MediaCollection::Episode(episode), hours_ago(1), 600))
+            .with_entry(fixture::played(MediaCollection::Movie(movie), hours_ago(2), 30))
+            .with_series(fixture::series("series-1", &["fantasy"]))
+            .with_movie_listing(fixture::movie_listing("listing-1", &["drama"]));
+
+        let history = fetch(&source).await;
+
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].media_type, MediaKind::Episode);
+        assert_eq!(history[0].title, "Frieren");
+        assert_eq!(history[0].episode_title.as_deref(), Some("The Journey's End"));
+        assert_eq!(history[0].series_id.as_deref(), Some("series-1"));
+        assert_eq!(history[0].playhead, Some(600));
+        assert_eq!(history[0].duration_ms, Some(24 * 60 * 1000));
+        assert_eq!(history[0].genres, vec![Genre::Fantasy]);
+        assert_eq!(history[1].media_type, MediaKind::Movie);
+        assert_eq!(history[1].movie_listing_id.as_deref(), Some("listing-1"));
+        assert_eq!(history[1].genres, vec![Genre::Drama]);
+    }
+
+    #[tokio::test]
+    async fn episode_metadata_is_kept_for_episodes_only() {
+        let mut episode = fixture::episode("ep-1", "series-1", "Frieren", "Ep 1");
+        episode.season_number = 1;
+        episode.episode_number = Some(1);
+        episode.sequence_number = 1.0;
+        episode.audio_locale = crunchyroll_rs::Locale::en_US;
+        episode.subtitle_locales = vec![crunchyroll_rs::Locale::de_DE];
+        episode.is_dubbed = true;
+        let movie = fixture::movie("movie-1", "listing-1", "Suzume");
+        let source = FixtureSource::new()
+            .with_entry(fixture::played(MediaCollection::Episode(episode), hours_ago(1), 0))
+            .with_entry(fixture::played(MediaCollection::Movie(movie), hours_ago(2), 0));
+
+        let history = fetch(&source).await;
+
+        let metadata = history[0].episode_metadata.as_ref().unwrap();
+        assert_eq!(metadata.episode_number, Some(1));
+        assert_eq!(metadata.audio_locale, "en-US");
+        assert_eq!(metadata.subtitle_locales, vec!["de-DE"]);
+        assert!(metadata.is_dubbed);
+        assert_eq!(metadata.air_date, None);
+        assert!(history[1].episode_metadata.is_none());
+    }
+
+    #[tokio::test]
+    async fn episode_categories_skip_series_lookup() {
+        let mut episode = fixture::episode("ep-1", "series-1", "Frieren", "Ep 1");
+        episode.categories = Some(fixture::categories(&["action"]));
+        let source = FixtureSource::new()
+            .with_entry(fixture::played(MediaCollection::Episode(episode), hours_ago(1), 0));
+
+        let history = fetch(&source).await;
+
+        assert_eq!(history[0].genres, vec![Genre::Action]);
+        assert_eq!(source.series_lookups(), 0);
+    }
+
+    #[tokio::test]
+    async fn series_genres_are_looked_up_once_per_series() {
+        let source = FixtureSource::new()
+            .with_entry(fixture::played(
+                MediaCollection::Episode(fixture::episode("ep-2", "series-1", "Frieren", "Ep 2")),
+                hours_ago(1),
+                0,
+            ))
+            .with_entry(fixture::played(
+                MediaCollection::Episode(fixture::episode("ep-1", "series-1", "Frieren", "Ep 1")),
+                hours_ago(2),
+                0,
+            ))
+            .with_series(fixture::series("series-1", &["fantasy"]));
+
+        let history = fetch(&source).await;
+
+        assert_eq!(history.len(), 2);
+        assert!(history.iter().all(|entry| entry.genres == vec![Genre::Fantasy]));
+        assert_eq!(source.series_lookups(), 1);
+    }
+
+    #[tokio::test]
+    async fn movie_listing_genres_are_looked_up_once_per_listing() {
+        let source = FixtureSource::new()
+            .with_entry(fixture::played(
+                MediaCollection::Movie(fixture::movie("movie-2", "listing-1", "Part 2")),
+                hours_ago(1),
+                0,
+            ))
+            .with_entry(fixture::played(
+                MediaCollection::Movie(fixture::movie("movie-1", "listing-1", "Part 1")),
+                hours_ago(2),
+                0,
+            ))
+            .with_movie_listing(fixture::movie_listing("listing-1", &["drama"]));
+
+        let history = fetch(&source).await;
+
+        assert!(history.iter().all(|entry| entry.genres == vec![Genre::Drama]));
+        assert_eq!(source.movie_listing_lookups(), 1);
+    }
+
+    #[tokio::test]
+    async fn failed_series_lookup_leaves_genres_empty() {
+        let source = FixtureSource::new().with_entry(fixture::played(
+            MediaCollection::Episode(fixture::episode("ep-1", "missing", "Frieren", "Ep 1")),
+            hours_ago(1),
+            0,
+        ));
+
+        let history = fetch(&source).await;
+
+        assert_eq!(history.len(), 1);
+        assert!(history[0].genres.is_empty());
+    }
+
+    #[tokio::test]
+    async fn missing_panel_resolved_by_entry_id() {
+        let episode = fixture::episode("ep-1", "series-1", "Frieren", "Ep 1");
+        let source = FixtureSource::new()
+            .with_entry(fixture::played_without_panel("ep-1", "series-1", hours_ago(1), 0))
+            .with_media("ep-1", MediaCollection::Episode(episode));
+
+        let history = fetch(&source).await;
+
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].content_id.as_deref(), Some("ep-1"));
+    }
+
+    #[tokio::test]
+    async fn missing_panel_falls_back_to_parent_id() {
+        let episode = fixture::episode("ep-1", "series-1", "Frieren", "Ep 1");
+        let source = FixtureSource::new()
+            .with_entry(fixture::played_without_panel("ep-1", "parent-1", hours_ago(1), 0))
+            .with_media("parent-1", MediaCollection::Episode(episode));
+
+        let history = fetch(&source).await;
+
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].title, "Frieren");
+    }
+
+    #[tokio::test]
+    async fn unresolvable_entries_are_skipped() {
+        let source = FixtureSource::new()
+            .with_entry(fixture::played_without_panel("ep-1", "parent-1", hours_ago(1), 0))
+            .with_entry(fixture::played_without_panel("ep-2", "ep-2", hours_ago(2), 0))
+            .with_entry(fixture::played(
+                MediaCollection::Episode(fixture::episode("ep-3", "series-1", "Frieren", "Ep 3")),
+                hours_ago(3),
+                0,
+            ));
+
+        let history = fetch(&source).await;
+
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].content_id.as_deref(), Some("ep-3"));
+    }
+
+    #[tokio::test]
+    async fn metadata_cache_is_shared_between_users() {
+        let episode = fixture::episode("ep-1", "series-1", "Frieren", "Ep 1");
+        let movie = fixture::movie("movie-1", "listing-1", "Suzume");
+        let source = FixtureSource::new()
+            .with_entry(fixture::played(MediaCollection::Episode(episode), hours_ago(1), 0))
+            .with_entry(fixture::played(MediaCollection::Movie(movie), hours_ago(2), 0))
+            .with_series(fixture::series("series-1", &["fantasy"]))
+            .with_movie_listing(fixture::movie_listing("listing-1", &["drama"]));
+        let cache = MetadataCache::new(CacheLimits::default());
+        let (alice, bob) = ("alice@example.com".to_string(), "bob@example.com".to_string());
+
+        for session in [&alice, &bob] {
+            let history = History::new(&source, session)
+                .with_metadata_cache(&cache)
+                .fetch_history(Some(100), &HistoryWindow::default())
+                .await
+                .unwrap();
+            assert_eq!(history[0].genres, vec![Genre::Fantasy]);
+            assert_eq!(history[1].genres, vec![Genre::Drama]);
+        }
+
+        assert_eq!((source.series_lookups(), source.movie_listing_lookups()), (1, 1));
+        let stats = cache.stats().await;
+        assert_eq!((stats.entries, stats.hits, stats.misses), (2, 2, 2));
+    }
+
+    #[tokio::test]
+    async fn series_title_fills_in_for_bare_panels() {
+        let mut series = fixture::series("series-1", &["fantasy"]);
+        series.title = "Frieren".to_string();
+        let source = FixtureSource::new()
+            .with_entry(fixture::played(
+                MediaCollection::Episode(fixture::episode("ep-1", "series-1", "", "Ep 1")),
+                hours_ago(1),
+                0,
+            ))
+            .with_series(series);
+
+        let history = fetch(&source).await;
+
+        assert_eq!(history[0].title, "Frieren");
+    }
+
+    #[tokio::test]
+    async fn failed_metadata_lookups_are_not_cached() {
+        let source = FixtureSource::new().with_entry(fixture::played(
+            MediaCollection::Episode(fixture::episode("ep-1", "series-1", "Frieren", "Ep 1")),
+            hours_ago(1),
+            0,
+        ));
+        let cache = MetadataCache::new(CacheLimits::default());
+        let session = EMAIL.to_string();
+        let history = History::new(&source, &session).with_metadata_cache(&cache);
+
+        history.fetch_history(Some(100), &HistoryWindow::default()).await.unwrap();
+        history.fetch_history(Some(100), &HistoryWindow::default()).await.unwrap();
+
+        assert_eq!(source.series_lookups(), 2);
+        assert_eq!(cache.stats().await.entries, 0);
+    }
+
+    #[tokio::test]
+    async fn pages_are_sent_as_they_resolve() {
+        let source = source_played_days_ago(&[1, 2, 3, 4, 5]);
+        let session = EMAIL.to_string();
+        let (pages, mut resolved) = tokio::sync::mpsc::unbounded_channel();
+
+        let history = History::new(&source, &session)
+            .with_pages(pages)
+            .fetch_history(Some(2), &HistoryWindow::default())
+            .await
+            .unwrap();
+
+        let mut sizes = Vec::new();
+        while let Ok(page) = resolved.try_recv() {
+            sizes.push(page.len());
+        }
+        assert_eq!(sizes, vec![2, 2, 1]);
+        assert_eq!(history.len(), 5);
+    }
+
+    #[tokio::test]
+    async fn slow_panel_lookups_keep_history_order() {
+        let source = ["ep-1", "ep-2", "ep-3"].iter().enumerate().fold(
+            FixtureSource::new(),
+            |source, (index, id)| {
+                source
+                    .with_entry(fixture::played_without_panel(id, id, hours_ago(index as i64 + 1), 0))
+                    .with_media(id, MediaCollection::Episode(fixture::episode(id, "series-1", "Frieren", id)))
+            },
+        )
+        .with_latency("ep-1", std::time::Duration::from_millis(50));
+
+        let history = fetch(&source).await;
+
+        let ids: Vec<_> = history.iter().map(|entry| entry.content_id.as_deref().unwrap()).collect();
+        assert_eq!(ids, vec!["ep-1", "ep-2", "ep-3"]);
+        assert!(source.max_in_flight() > 1);
+    }
+
+    #[tokio::test]
+    async fn lookups_stay_within_the_concurrency_limit() {
+        let source = (0..6)
+            .fold(FixtureSource::new(), |source, index| {
+                let id = format!("ep-{}", index);
+                let series_id = format!("series-{}", index);
+                source
+                    .with_entry(fixture::played_without_panel(&id, &id, hours_ago(index + 1), 0))
+                    .with_media(&id, MediaCollection::Episode(fixture::episode(&id, &series_id, "Frieren", &id)))
+                    .with_latency(&id, std::time::Duration::from_millis(10))
+                    .with_latency(&series_id, std::time::Duration::from_millis(10))
+            })
+            .with_metadata_concurrency(2);
+
+        let history = fetch(&source).await;
+
+        assert_eq!(history.len(), 6);
+        assert_eq!(source.series_lookups(), 6);
+        assert_eq!(source.max_in_flight(), 2);
+    }
+
+    #[tokio::test]
+    async fn ids_follow_the_play_not_the_position() {
+        let played = hours_ago(2);
+        let episode = |id: &str| MediaCollection::Episode(fixture::episode(id, "series-1", "Frieren", id));
+        let before = FixtureSource::new().with_entry(fixture::played(episode("ep-1"), played, 0));
+        let after = FixtureSource::new()
+            .with_entry(fixture::played(episode("ep-2"), hours_ago(1), 0))
+            .with_entry(fixture::played(episode("ep-1"), played, 0));
+
+        let before = fetch(&before).await;
+        let after = fetch(&after).await;
+
+        assert_eq!(before[0].id, stable_id("ep-1", played));
+        assert_eq!(after[1].id, before[0].id);
+        assert_ne!(after[0].id, after[1].id);
+    }
+
+    #[tokio::test]
+    async fn stops_at_365_day_cutoff() {
+        let source = FixtureSource::new()
+            .with_entry(fixture::played(
+                MediaCollection::Episode(fixture::episode("ep-2", "series-1", "Frieren", "Ep 2")),
+                Utc::now() - Duration::days(364),
+                0,
+            ))
+            .with_entry(fixture::played(
+                MediaCollection::Episode(fixture::episode("ep-1", "series-1", "Frieren", "Ep 1")),
+                Utc::now() - Duration::days(366),
+                0,
+            ));
+
+        let history = fetch(&source).await;
+
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].content_id.as_deref(), Some("ep-2"));
+    }
+
+    #[tokio::test]
+    async fn all_window_has_no_cutoff() {
+        let source = source_played_days_ago(&[1, 400, 3000]);
+
+        let history = fetch_window(
+            &source,
+            HistoryWindow {
+                since: Since::All,
+                until: None,
+            },
+        )
+        .await;
+
+        assert_eq!(history.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn since_and_until_bound_the_window() {
+        let source = source_played_days_ago(&[1, 10, 20, 30]);
+
+        let history = fetch_window(
+            &source,
+            HistoryWindow {
+                since: Since::At(days_ago(25)),
+                until: Some(days_ago(5)),
+            },
+        )
+        .await;
+
+        let ids: Vec<_> = history.iter().filter_map(|entry| entry.content_id.as_deref()).collect();
+        assert_eq!(ids, vec!["ep-1", "ep-2"]);
+    }
+
+    #[test]
+    fn window_display_is_stable_and_distinct() {
+        let until = Utc::now();
+        let windows = [
+            HistoryWindow::default(),
+            HistoryWindow { since: Since::All, until: None },
+            HistoryWindow { since: Since::Days(30), until: None },
+            HistoryWindow { since: Since::All, until: Some(until) },
+        ];
+
+        assert_eq!(HistoryWindow::default().to_string(), "since=365d,until=now");
+        for (i, a) in windows.iter().enumerate() {
+            for b in &windows[i + 1..] {
+                assert_ne!(a.to_string(), b.to_string());
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn fetch_newer_than_stops_at_known_entry() {
+        let source = source_played_days_ago(&[1, 2, 3]);
+        let session = EMAIL.to_string();
+
+        let history = History::new(&source, &session)
+            .fetch_newer_than(Some(100), Some(days_ago(2)))
+            .await
+            .unwrap();
+
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].content_id.as_deref(), Some("ep-0"));
+    }
+
+    #[tokio::test]
+    async fn catalogues_are_fetched_once_per_series() {
+        // The dub is listed as its own season and as a version of season 1.
+        let mut season_1 = fixture::season("season-1", "series-1", 1);
+        let dub = fixture::season("season-1-dub", "series-1", 1);
+        let mut dub_version = crunchyroll_rs::media::SeasonVersion::default();
+        dub_version.id = dub.id.clone();
+        season_1.versions = vec![dub_version];
+        let source = source_played_days_ago(&[1, 2])
+            .with_series(fixture::series("series-1", &[]))
+            .with_season(
+                season_1,
+                vec![
+                    fixture::episode("ep-0", "series-1", "Frieren", "Ep 1"),
+                    fixture::episode("ep-1", "series-1", "Frieren", "Ep 2"),
+                ],
+            )
+            .with_season(dub, vec![fixture::episode("ep-0-dub", "series-1", "Frieren", "Ep 1")]);
+        let session = EMAIL.to_string();
+        let history = History::new(&source, &session);
+        let entries = history.fetch_history(Some(100), &HistoryWindow::default()).await.unwrap();
+
+        let catalogues = history.fetch_catalogues(&entries).await;
+
+        assert_eq!(catalogues.len(), 1);
+        assert_eq!(catalogues[0].seasons.len(), 1);
+        assert_eq!(catalogues[0].seasons[0].episodes, vec![vec!["ep-0"], vec!["ep-1"]]);
+        assert_eq!(source.episode_lookups(), 1);
+    }
+
+    #[tokio::test]
+    async fn catalogues_are_shared_and_fetched_concurrently() {
+        let source = (0..4)
+            .fold(FixtureSource::new(), |source, index| {
+                let id = format!("ep-{}", index);
+                let series_id = format!("series-{}", index);
+                let season_id = format!("season-{}", index);
+                source
+                    .with_entry(fixture::played(
+                        MediaCollection::Episode(fixture::episode(&id, &series_id, "Frieren", &id)),
+                        hours_ago(index + 1),
+                        0,
+                    ))
+                    .with_series(fixture::series(&series_id, &["fantasy"]))
+                    .with_season(
+                        fixture::season(&season_id, &series_id, 1),
+                        vec![fixture::episode(&id, &series_id, "Frieren", &id)],
+                    )
+                    .with_latency(&series_id, std::time::Duration::from_millis(10))
+            })
+            .with_metadata_concurrency(2);
+        let cache = MetadataCache::new(CacheLimits::default());
+        let (alice, bob) = ("alice@example.com".to_string(), "bob@example.com".to_string());
+
+        for session in [&alice, &bob] {
+            let history = History::new(&source, session).with_metadata_cache(&cache);
+            let entries = history.fetch_history(Some(100), &HistoryWindow::default()).await.unwrap();
+            let catalogues = history.fetch_catalogues(&entries).await;
+            let series: Vec<_> = catalogues.iter().map(|catalogue| catalogue.series_id.as_str()).collect();
+            assert_eq!(series, vec!["series-0", "series-1", "series-2", "series-3"]);
+        }
+
+        assert_eq!(source.episode_lookups(), 4);
+        assert_eq!(source.max_in_flight(), 2);
+        assert_eq!(cache.stats().await.catalogues, 4);
+    }
+
+    #[tokio::test]
+    async fn series_without_catalogue_are_left_out() {
+        let source = source_played_days_ago(&[1]);
+        let session = EMAIL.to_string();
+        let history = History::new(&source, &session);
+        let entries = history.fetch_history(Some(100), &HistoryWindow::default()).await.unwrap();
+
+        assert!(history.fetch_catalogues(&entries).await.is_empty());
+    }
+
+    fn classified(playhead: Option<u32>, duration_ms: Option<u64>, threshold: f64) -> HistoryEntry {
+        let mut entry = HistoryEntry {
+            playhead,
+            duration_ms,
+            ..HistoryEntry::test("item-0", None)
+        };
+        classify(&mut entry, threshold);
+        entry
+    }
+
+    #[test]
+    fn classify_caps_playhead_and_derives_status() {
+        let full = classified(Some(1500), Some(1_440_000), DEFAULT_COMPLETION_THRESHOLD);
+        let credits_skipped = classified(Some(1300), Some(1_440_000), DEFAULT_COMPLETION_THRESHOLD);
+        let half = classified(Some(720), Some(1_440_000), DEFAULT_COMPLETION_THRESHOLD);
+        let peek = classified(Some(30), Some(1_440_000), DEFAULT_COMPLETION_THRESHOLD);
+
+        assert_eq!((full.watched_ms, full.watch_status), (1_440_000, WatchStatus::Completed));
+        assert_eq!(credits_skipped.watch_status, WatchStatus::Completed);
+        assert_eq!((half.watched_ms, half.watch_status), (720_000, WatchStatus::Partial));
+        assert_eq!(peek.watch_status, WatchStatus::Sampled);
+    }
+
+    #[test]
+    fn classify_threshold_is_configurable() {
+        assert_eq!(classified(Some(720), Some(1_440_000), 0.5).watch_status, WatchStatus::Completed);
+        assert_eq!(classified(Some(1300), Some(1_440_000), 1.0).watch_status, WatchStatus::Partial);
+    }
+
+    #[test]
+    fn classify_without_duration_never_completes() {
+        let entry = classified(Some(1500), None, DEFAULT_COMPLETION_THRESHOLD);
+        assert_eq!((entry.watched_ms, entry.watch_status), (1_500_000, WatchStatus::Partial));
+        assert_eq!(classified(None, None, DEFAULT_COMPLETION_THRESHOLD).watch_status, WatchStatus::Sampled);
+    }
+
+    #[test]
+    fn short_entries_watched_to_the_end_are_completed() {
+        let entry = classified(Some(90), Some(90_000), DEFAULT_COMPLETION_THRESHOLD);
+        assert_eq!(entry.watch_status, WatchStatus::Completed);
     }
 }
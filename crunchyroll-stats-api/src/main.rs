@@ -1,21 +1,43 @@
+mod admin;
 mod auth;
 mod cache;
+mod crypto;
 mod history;
 mod models;
 mod rate_limit;
+mod session;
+mod source;
+mod stats;
+mod store;
+mod sync;
 
 use actix_cors::Cors;
-use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer, Result};
+use actix_web::{http::header, web, App, HttpRequest, HttpResponse, HttpServer, Result};
+use std::collections::HashMap;
 use std::env;
 use std::net::IpAddr;
+use std::sync::Arc;
+use admin::AdminToken;
 use auth::CrunchyrollClient;
 use cache::AppCache;
-use models::{AuthResponse, ErrorResponse, HealthResponse, HistoryResponse, LoginRequest};
+use crypto::HistoryKey;
+use history::{History, HistoryWindow, Since};
+use models::{
+    AuthResponse, BingeQuery, CacheInvalidation, CacheReport, CachedHistoryReport, ErrorResponse, Freshness, HealthResponse, HistoryEntry, HistoryEvent,
+    HistoryCacheReport, HistoryQuery, HistoryResponse, MetadataCacheReport,
+    LoginRequest, SessionSummary, SessionsResponse, StatsResponse, SuccessResponse, TimezoneQuery,
+};
 use rate_limit::RateLimiter;
+use session::{SessionStore, UserSession};
+use source::{CrunchyrollSource, WatchDataSource};
+use store::HistoryStore;
+use tokio::sync::mpsc;
 use tracing_actix_web::TracingLogger;
 use validator::Validate;
 use zeroize::Zeroize;
 
+const HISTORY_PAGE_SIZE: Option<usize> = Some(100); // Entries per Crunchyroll request
+
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     dotenvy::dotenv().ok();
@@ -31,8 +53,15 @@ async fn main() -> std::io::Result<()> {
     let port = env::var("PORT").unwrap_or_else(|_| "8080".to_string());
     let bind_address = format!("{}:{}", host, port);
 
-    let cache = AppCache::new();
+    let cache = AppCache::from_env().map_err(std::io::Error::other)?;
     let rate_limiter = RateLimiter::new();
+    let source = web::Data::new(CrunchyrollSource::from_env().map_err(std::io::Error::other)?);
+    let sessions = SessionStore::<CrunchyrollClient>::new();
+    let store = HistoryStore::from_env().map_err(std::io::Error::other)?;
+    let admin = web::Data::new(AdminToken::from_env().map_err(std::io::Error::other)?);
+    if !admin.is_enabled() {
+        tracing::info!("ADMIN_TOKEN not set, admin routes are disabled");
+    }
 
     tracing::info!(bind = %bind_address, "Starting Crunchyroll API Server");
 
@@ -58,15 +87,35 @@ async fn main() -> std::io::Result<()> {
             )
             .app_data(web::Data::from(cache.clone()))
             .app_data(web::Data::from(rate_limiter.clone()))
-            .route("/health", web::get().to(health_check))
-            .route("/api/auth", web::post().to(validate_credentials))
-            .route("/api/watch-history", web::post().to(get_watch_history))
+            .app_data(source.clone())
+            .app_data(web::Data::from(sessions.clone()))
+            .app_data(web::Data::from(store.clone()))
+            .app_data(admin.clone())
+            .configure(routes::<CrunchyrollSource>)
     })
     .bind(&bind_address)?
     .run()
     .await
 }
 
+fn routes<S: WatchDataSource>(cfg: &mut web::ServiceConfig) {
+    cfg.route("/health", web::get().to(health_check))
+        .route("/api/auth", web::post().to(validate_credentials::<S>))
+        .route("/api/logout", web::post().to(logout::<S>))
+        .route("/api/sessions", web::get().to(list_sessions::<S>))
+        .route("/api/sessions/{id}", web::delete().to(revoke_session::<S>))
+        .route("/api/watch-history", web::get().to(get_watch_history::<S>))
+        .route("/api/watch-history/stream", web::get().to(stream_watch_history::<S>))
+        .route("/api/stats/summary", web::get().to(get_stats_summary::<S>))
+        .route("/api/stats/streaks", web::get().to(get_stats_streaks::<S>))
+        .route("/api/stats/binges", web::get().to(get_stats_binges::<S>))
+        .route("/api/stats/completion", web::get().to(get_stats_completion::<S>))
+        .route("/api/stats/rewatches", web::get().to(get_stats_rewatches::<S>))
+        .route("/api/admin/cache", web::get().to(get_cache_report))
+        .route("/api/admin/cache", web::delete().to(flush_cache))
+        .route("/api/admin/cache/users/{user_key}", web::delete().to(invalidate_user_cache));
+}
+
 async fn health_check() -> Result<HttpResponse> {
     Ok(HttpResponse::Ok().json(HealthResponse {
         status: "ok".to_string(),
@@ -80,9 +129,86 @@ fn peer_ip(req: &HttpRequest) -> IpAddr {
         .unwrap_or(IpAddr::V4(std::net::Ipv4Addr::UNSPECIFIED))
 }
 
-async fn validate_credentials(
+fn bearer_token(req: &HttpRequest) -> Option<&str> {
+    req.headers()
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+        .map(str::trim)
+        .filter(|token| !token.is_empty())
+}
+
+/// Resolves the request's bearer token to a live session. A missing or
+/// unknown token counts as a failed attempt for rate limiting.
+async fn authorize<T>(
+    http_req: &HttpRequest,
+    sessions: &SessionStore<T>,
+    limiter: &RateLimiter,
+) -> std::result::Result<Arc<UserSession<T>>, HttpResponse> {
+    let ip = peer_ip(http_req);
+
+    if limiter.is_blocked(ip).await {
+        tracing::warn!(ip = %ip, event = "rate_limited");
+        return Err(HttpResponse::TooManyRequests().json(ErrorResponse {
+            error: "Too many failed attempts. Try again later.".to_string(),
+        }));
+    }
+
+    let session = match bearer_token(http_req) {
+        Some(token) => sessions.get(token).await,
+        None => None,
+    };
+    match session {
+        Some(session) => Ok(session),
+        None => {
+            tracing::warn!(ip = %ip, event = "invalid_session");
+            limiter.record_failure(ip).await;
+            Err(HttpResponse::Unauthorized().json(ErrorResponse {
+                error: "Invalid or expired session".to_string(),
+            }))
+        }
+    }
+}
+
+/// Checks the request's bearer token against `ADMIN_TOKEN`. The admin
+/// routes are not found at all when no token is configured, and a wrong
+/// token counts as a failed attempt for rate limiting.
+async fn authorize_admin(
+    http_req: &HttpRequest,
+    admin: &AdminToken,
+    limiter: &RateLimiter,
+) -> std::result::Result<(), HttpResponse> {
+    let ip = peer_ip(http_req);
+
+    if !admin.is_enabled() {
+        return Err(HttpResponse::NotFound().json(ErrorResponse {
+            error: "Not found".to_string(),
+        }));
+    }
+
+    if limiter.is_blocked(ip).await {
+        tracing::warn!(ip = %ip, event = "rate_limited");
+        return Err(HttpResponse::TooManyRequests().json(ErrorResponse {
+            error: "Too many failed attempts. Try again later.".to_string(),
+        }));
+    }
+
+    if bearer_token(http_req).is_some_and(|token| admin.matches(token)) {
+        return Ok(());
+    }
+    tracing::warn!(ip = %ip, event = "invalid_admin_token");
+    limiter.record_failure(ip).await;
+    Err(HttpResponse::Unauthorized().json(ErrorResponse {
+        error: "Invalid admin token".to_string(),
+    }))
+}
+
+async fn validate_credentials<S: WatchDataSource>(
     http_req: HttpRequest,
     req: web::Json<LoginRequest>,
+    source: web::Data<S>,
+    sessions: web::Data<SessionStore<S::Session>>,
     limiter: web::Data<RateLimiter>,
 ) -> Result<HttpResponse> {
     let ip = peer_ip(&http_req);
@@ -104,14 +230,40 @@ async fn validate_credentials(
         }));
     }
 
-    let result = CrunchyrollClient::new(&login.email, &login.password).await;
+    let user_key = AppCache::cache_key(&login.email);
+    let result = match source.login(&login.email, &login.password).await {
+        // Derived only after Crunchyroll accepted the password, so failed
+        // attempts never pay for the key derivation.
+        Ok(upstream) => {
+            let password = login.password.clone();
+            let salt = user_key.clone();
+            let derived = web::block(move || {
+                let mut password = password;
+                let key = HistoryKey::derive(&password, &salt);
+                password.zeroize();
+                key
+            })
+            .await;
+            match derived {
+                Ok(Ok(history_key)) => Ok((upstream, history_key)),
+                Ok(Err(e)) => Err(e),
+                Err(e) => Err(e.into()),
+            }
+        }
+        Err(e) => Err(e),
+    };
     login.zeroize();
 
     match result {
-        Ok(_) => {
+        Ok((upstream, history_key)) => {
             tracing::info!(ip = %ip, event = "auth_success");
             limiter.record_success(ip).await;
-            Ok(HttpResponse::Ok().json(AuthResponse { success: true }))
+            let (token, session) = sessions.create(user_key, history_key, upstream).await;
+            Ok(HttpResponse::Ok().json(AuthResponse {
+                success: true,
+                token,
+                session_id: session.id.clone(),
+            }))
         }
         Err(e) => {
             tracing::warn!(ip = %ip, event = "auth_failed", error = %e);
@@ -123,77 +275,1607 @@ async fn validate_credentials(
     }
 }
 
-async fn get_watch_history(
+async fn logout<S: WatchDataSource>(
     http_req: HttpRequest,
-    req: web::Json<LoginRequest>,
-    cache: web::Data<AppCache>,
+    sessions: web::Data<SessionStore<S::Session>>,
     limiter: web::Data<RateLimiter>,
 ) -> Result<HttpResponse> {
     let ip = peer_ip(&http_req);
+    let session = match authorize(&http_req, &sessions, &limiter).await {
+        Ok(session) => session,
+        Err(response) => return Ok(response),
+    };
 
-    if limiter.is_blocked(ip).await {
-        tracing::warn!(ip = %ip, event = "rate_limited");
-        return Ok(HttpResponse::TooManyRequests().json(ErrorResponse {
-            error: "Too many failed attempts. Try again later.".to_string(),
-        }));
+    // authorize() only succeeds with a token present.
+    if let Some(token) = bearer_token(&http_req) {
+        sessions.revoke(token).await;
     }
+    tracing::info!(ip = %ip, event = "logout", session = %session.id);
+    Ok(HttpResponse::Ok().json(SuccessResponse { success: true }))
+}
 
-    // Extract credentials and drop the request wrapper immediately.
-    let mut login = req.into_inner();
+/// The caller's live sessions across devices, so they can find the id of
+/// one to revoke.
+async fn list_sessions<S: WatchDataSource>(
+    http_req: HttpRequest,
+    sessions: web::Data<SessionStore<S::Session>>,
+    limiter: web::Data<RateLimiter>,
+) -> Result<HttpResponse> {
+    let session = match authorize(&http_req, &sessions, &limiter).await {
+        Ok(session) => session,
+        Err(response) => return Ok(response),
+    };
 
-    if let Err(e) = login.validate() {
-        tracing::warn!(ip = %ip, event = "validation_failed", error = %e);
-        login.zeroize();
+    let listed = sessions
+        .list_for(&session.user_key)
+        .await
+        .into_iter()
+        .map(|info| SessionSummary {
+            current: info.id == session.id,
+            id: info.id,
+            created_at: info.created_at,
+            last_used_at: info.last_used_at,
+        })
+        .collect();
+    Ok(HttpResponse::Ok().json(SessionsResponse { sessions: listed }))
+}
+
+async fn revoke_session<S: WatchDataSource>(
+    http_req: HttpRequest,
+    path: web::Path<String>,
+    sessions: web::Data<SessionStore<S::Session>>,
+    limiter: web::Data<RateLimiter>,
+) -> Result<HttpResponse> {
+    let ip = peer_ip(&http_req);
+    let session = match authorize(&http_req, &sessions, &limiter).await {
+        Ok(session) => session,
+        Err(response) => return Ok(response),
+    };
+
+    let id = path.into_inner();
+    if sessions.revoke_by_id(&session.user_key, &id).await {
+        tracing::info!(ip = %ip, event = "session_revoked", session = %id);
+        Ok(HttpResponse::Ok().json(SuccessResponse { success: true }))
+    } else {
+        Ok(HttpResponse::NotFound().json(ErrorResponse {
+            error: "Session not found".to_string(),
+        }))
+    }
+}
+
+async fn get_cache_report(
+    http_req: HttpRequest,
+    admin: web::Data<AdminToken>,
+    cache: web::Data<AppCache>,
+    limiter: web::Data<RateLimiter>,
+) -> Result<HttpResponse> {
+    if let Err(response) = authorize_admin(&http_req, &admin, &limiter).await {
+        return Ok(response);
+    }
+
+    let (history, entries) = match cache.history_report().await {
+        Ok(report) => report,
+        Err(error) => return Ok(cache_unavailable(error)),
+    };
+    let limits = cache.limits();
+    let cached = entries
+        .into_iter()
+        .map(|entry| CachedHistoryReport {
+            key: entry.key,
+            age_seconds: entry.age.as_secs(),
+            stale: entry.stale,
+            items: entry.items,
+            bytes: entry.bytes,
+        })
+        .collect();
+    let metadata = cache.metadata().stats().await;
+
+    Ok(HttpResponse::Ok().json(CacheReport {
+        history: HistoryCacheReport {
+            entries: history.entries,
+            bytes: history.bytes,
+            max_entries: limits.map(|limits| limits.max_entries),
+            max_bytes: limits.map(|limits| limits.max_bytes),
+            hits: history.hits,
+            misses: history.misses,
+            hit_ratio: models::cache::hit_ratio(history.hits, history.misses),
+            evictions: history.evictions,
+            expirations: history.expirations,
+            cached,
+        },
+        metadata: MetadataCacheReport {
+            entries: metadata.entries,
+            catalogues: metadata.catalogues,
+            bytes: metadata.bytes,
+            evictions: metadata.evictions,
+            hits: metadata.hits,
+            misses: metadata.misses,
+            hit_ratio: models::cache::hit_ratio(metadata.hits, metadata.misses),
+        },
+    }))
+}
+
+/// Drops every cached window of the user whose hashed key is in the path.
+/// Their synced history in the store is kept.
+async fn invalidate_user_cache(
+    http_req: HttpRequest,
+    path: web::Path<String>,
+    admin: web::Data<AdminToken>,
+    cache: web::Data<AppCache>,
+    limiter: web::Data<RateLimiter>,
+) -> Result<HttpResponse> {
+    if let Err(response) = authorize_admin(&http_req, &admin, &limiter).await {
+        return Ok(response);
+    }
+
+    let user_key = path.into_inner();
+    if user_key.len() != 64 || !user_key.chars().all(|c| c.is_ascii_hexdigit()) {
         return Ok(HttpResponse::BadRequest().json(ErrorResponse {
-            error: "Invalid request".to_string(),
+            error: "user_key must be a 64 character hex string".to_string(),
         }));
     }
 
-    let cache_key = AppCache::cache_key(&login.email);
-    let force_refresh = login.force_refresh;
+    let removed = match cache.invalidate_user(&user_key.to_ascii_lowercase()).await {
+        Ok(removed) => removed,
+        Err(error) => return Ok(cache_unavailable(error)),
+    };
+    tracing::info!(ip = %peer_ip(&http_req), event = "admin_cache_invalidated", removed);
+    Ok(HttpResponse::Ok().json(CacheInvalidation { removed }))
+}
 
-    // Check cache first (skip on force refresh)
-    if !force_refresh {
-        if let Some(cached) = cache.get_history(&cache_key).await {
-            tracing::info!(ip = %ip, event = "cache_hit", items = cached.len());
-            return Ok(HttpResponse::Ok().json(HistoryResponse { data: cached }));
+async fn flush_cache(
+    http_req: HttpRequest,
+    admin: web::Data<AdminToken>,
+    cache: web::Data<AppCache>,
+    limiter: web::Data<RateLimiter>,
+) -> Result<HttpResponse> {
+    if let Err(response) = authorize_admin(&http_req, &admin, &limiter).await {
+        return Ok(response);
+    }
+
+    let removed = match cache.flush().await {
+        Ok(removed) => removed,
+        Err(error) => return Ok(cache_unavailable(error)),
+    };
+    tracing::info!(ip = %peer_ip(&http_req), event = "admin_cache_flushed", removed);
+    Ok(HttpResponse::Ok().json(CacheInvalidation { removed }))
+}
+
+fn cache_unavailable(error: anyhow::Error) -> HttpResponse {
+    tracing::error!(event = "cache_backend_failed", error = %error);
+    HttpResponse::ServiceUnavailable().json(ErrorResponse {
+        error: "Cache backend unavailable".to_string(),
+    })
+}
+
+async fn get_watch_history<S: WatchDataSource>(
+    http_req: HttpRequest,
+    query: web::Query<HistoryQuery>,
+    source: web::Data<S>,
+    sessions: web::Data<SessionStore<S::Session>>,
+    cache: web::Data<AppCache>,
+    store: web::Data<HistoryStore>,
+    limiter: web::Data<RateLimiter>,
+) -> Result<HttpResponse> {
+    match load_history::<S>(&http_req, &query, &source, &sessions, &cache, &store, &limiter).await {
+        Ok((_, data, freshness)) => Ok(HttpResponse::Ok().json(HistoryResponse { data, freshness })),
+        Err(response) => Ok(response),
+    }
+}
+
+/// Like `get_watch_history`, but sends entries as each page is resolved
+/// instead of once the whole history is in, with progress in between.
+/// Server-sent events when the client accepts `text/event-stream`, NDJSON
+/// otherwise.
+async fn stream_watch_history<S: WatchDataSource>(
+    http_req: HttpRequest,
+    query: web::Query<HistoryQuery>,
+    source: web::Data<S>,
+    sessions: web::Data<SessionStore<S::Session>>,
+    cache: web::Data<AppCache>,
+    store: web::Data<HistoryStore>,
+    limiter: web::Data<RateLimiter>,
+) -> Result<HttpResponse> {
+    let ip = peer_ip(&http_req);
+    let session = match authorize(&http_req, &sessions, &limiter).await {
+        Ok(session) => session,
+        Err(response) => return Ok(response),
+    };
+    let (window, completion_threshold) = match history_params(ip, &query) {
+        Ok(params) => params,
+        Err(response) => return Ok(response),
+    };
+    let format = EventFormat::of(&http_req);
+
+    let (events, receiver) = mpsc::unbounded_channel();
+    match cached_history(ip, query.force_refresh, &session, &cache, &store, &window).await {
+        Some((entries, freshness)) => {
+            if freshness.stale {
+                refresh_in_background(&source, &session, &cache, &store, window);
+            }
+            let items = entries.len();
+            let _ = events.send(HistoryEvent::Entries {
+                entries: reclassify(entries, completion_threshold),
+            });
+            let _ = events.send(HistoryEvent::Done { items, freshness });
+        }
+        None => {
+            tracing::info!(ip = %ip, event = "fetch_start");
+            actix_web::rt::spawn(stream_fetch(
+                source.into_inner(),
+                session,
+                cache.into_inner(),
+                store.into_inner(),
+                window,
+                completion_threshold,
+                events,
+            ));
         }
-    } else {
-        tracing::info!(ip = %ip, event = "cache_bypass_forced");
     }
 
-    tracing::info!(ip = %ip, event = "fetch_start");
-    let limit = Some(100);
+    let body = futures_util::stream::unfold(receiver, move |mut receiver| async move {
+        let event = receiver.recv().await?;
+        Some((Ok::<_, actix_web::Error>(format.encode(&event)), receiver))
+    });
+    Ok(HttpResponse::Ok()
+        .content_type(format.content_type())
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        .streaming(body))
+}
 
-    // Authenticate and fetch, then zero out credentials before processing result.
-    let result = fetch_watch_history(&login.email, &login.password, limit).await;
-    login.zeroize();
+/// Syncs the history like `load_history` does, sending each page to
+/// `events` as it is resolved and whatever the sync changed or added from
+/// the store at the end.
+async fn stream_fetch<S: WatchDataSource>(
+    source: Arc<S>,
+    session: Arc<UserSession<S::Session>>,
+    cache: Arc<AppCache>,
+    store: Arc<HistoryStore>,
+    window: HistoryWindow,
+    completion_threshold: f64,
+    events: mpsc::UnboundedSender<HistoryEvent>,
+) {
+    let (pages, mut resolved) = mpsc::unbounded_channel();
+    let history = History::new(source.as_ref(), &session.upstream)
+        .with_metadata_cache(cache.metadata())
+        .with_pages(pages);
+    let fetch = fetch_watch_history(&history, &session, &cache, &store, HISTORY_PAGE_SIZE, &window);
+    tokio::pin!(fetch);
+
+    let cutoff = window.cutoff();
+    let mut sent = HashMap::new();
+    let mut fetched = 0;
+    let mut oldest_played_at = None;
+    let mut forward = |page: Vec<HistoryEntry>| {
+        fetched += page.len();
+        oldest_played_at = page.iter().filter_map(HistoryEntry::played_at).min().or(oldest_played_at);
+        // Full syncs fetch up to now; only the window goes out.
+        let entries: Vec<HistoryEntry> = reclassify(page, completion_threshold)
+            .into_iter()
+            .filter(|entry| entry.played_at().is_some_and(|played| window.contains(played)))
+            .collect();
+        for entry in &entries {
+            sent.insert(entry.id.clone(), (entry.rewatch_count, entry.first_watched_at.clone()));
+        }
+        if !entries.is_empty() {
+            let _ = events.send(HistoryEvent::Entries { entries });
+        }
+        let now = chrono::Utc::now();
+        let fraction = cutoff.zip(oldest_played_at).map(|(cutoff, oldest)| {
+            let total = (now - cutoff).num_seconds().max(1) as f64;
+            ((now - oldest).num_seconds() as f64 / total).clamp(0.0, 1.0)
+        });
+        let _ = events.send(HistoryEvent::Progress {
+            fetched,
+            oldest_played_at,
+            fraction,
+        });
+    };
+
+    let result = loop {
+        tokio::select! {
+            biased;
+            Some(page) = resolved.recv() => forward(page),
+            result = &mut fetch => break result,
+        }
+    };
+    while let Ok(page) = resolved.try_recv() {
+        forward(page);
+    }
 
     match result {
+        Ok(data) => {
+            tracing::info!(event = "fetch_success", items = data.len());
+            let data = reclassify(data, completion_threshold);
+            let items = data.len();
+            let changed: Vec<HistoryEntry> = data
+                .into_iter()
+                .filter(|entry| {
+                    sent.get(&entry.id) != Some(&(entry.rewatch_count, entry.first_watched_at.clone()))
+                })
+                .collect();
+            if !changed.is_empty() {
+                let _ = events.send(HistoryEvent::Entries { entries: changed });
+            }
+            let _ = events.send(HistoryEvent::Done {
+                items,
+                freshness: Freshness::default(),
+            });
+        }
+        Err(e) => {
+            tracing::error!(event = "fetch_failed", error = %e);
+            let _ = events.send(HistoryEvent::Error {
+                error: "Failed to fetch watch history".to_string(),
+            });
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EventFormat {
+    Ndjson,
+    ServerSentEvents,
+}
+
+impl EventFormat {
+    fn of(req: &HttpRequest) -> Self {
+        let accepts_sse = req
+            .headers()
+            .get(header::ACCEPT)
+            .and_then(|accept| accept.to_str().ok())
+            .is_some_and(|accept| accept.contains("text/event-stream"));
+        if accepts_sse {
+            EventFormat::ServerSentEvents
+        } else {
+            EventFormat::Ndjson
+        }
+    }
+
+    fn content_type(self) -> &'static str {
+        match self {
+            EventFormat::Ndjson => "application/x-ndjson",
+            EventFormat::ServerSentEvents => "text/event-stream",
+        }
+    }
+
+    fn encode(self, event: &HistoryEvent) -> web::Bytes {
+        let json = serde_json::to_string(event).expect("history events serialize");
+        match self {
+            EventFormat::Ndjson => web::Bytes::from(format!("{}\n", json)),
+            EventFormat::ServerSentEvents => {
+                web::Bytes::from(format!("event: {}\ndata: {}\n\n", event.name(), json))
+            }
+        }
+    }
+}
+
+async fn get_stats_summary<S: WatchDataSource>(
+    http_req: HttpRequest,
+    query: web::Query<HistoryQuery>,
+    source: web::Data<S>,
+    sessions: web::Data<SessionStore<S::Session>>,
+    cache: web::Data<AppCache>,
+    store: web::Data<HistoryStore>,
+    limiter: web::Data<RateLimiter>,
+) -> Result<HttpResponse> {
+    match load_history::<S>(&http_req, &query, &source, &sessions, &cache, &store, &limiter).await {
+        Ok((_, entries, freshness)) => Ok(HttpResponse::Ok().json(StatsResponse {
+            data: stats::summarize(&entries),
+            freshness,
+        })),
+        Err(response) => Ok(response),
+    }
+}
+
+async fn get_stats_streaks<S: WatchDataSource>(
+    http_req: HttpRequest,
+    query: web::Query<HistoryQuery>,
+    source: web::Data<S>,
+    sessions: web::Data<SessionStore<S::Session>>,
+    cache: web::Data<AppCache>,
+    store: web::Data<HistoryStore>,
+    limiter: web::Data<RateLimiter>,
+) -> Result<HttpResponse> {
+    let session = match authorize(&http_req, &sessions, &limiter).await {
+        Ok(session) => session,
+        Err(response) => return Ok(response),
+    };
+    let tz = match web::Query::<TimezoneQuery>::from_query(http_req.query_string())
+        .ok()
+        .and_then(|tz_query| tz_query.tz.as_deref().unwrap_or("UTC").parse::<chrono_tz::Tz>().ok())
+    {
+        Some(tz) => tz,
+        None => {
+            return Ok(HttpResponse::BadRequest().json(ErrorResponse {
+                error: "Unknown timezone".to_string(),
+            }))
+        }
+    };
+
+    match load_session_history(&http_req, &query, &source, session, &cache, &store).await {
+        Ok((_, entries, freshness)) => {
+            let today = chrono::Utc::now().with_timezone(&tz).date_naive();
+            Ok(HttpResponse::Ok().json(StatsResponse {
+                data: stats::streaks(&entries, tz, today),
+                freshness,
+            }))
+        }
+        Err(response) => Ok(response),
+    }
+}
+
+async fn get_stats_binges<S: WatchDataSource>(
+    http_req: HttpRequest,
+    query: web::Query<HistoryQuery>,
+    source: web::Data<S>,
+    sessions: web::Data<SessionStore<S::Session>>,
+    cache: web::Data<AppCache>,
+    store: web::Data<HistoryStore>,
+    limiter: web::Data<RateLimiter>,
+) -> Result<HttpResponse> {
+    let session = match authorize(&http_req, &sessions, &limiter).await {
+        Ok(session) => session,
+        Err(response) => return Ok(response),
+    };
+    let binge_query = match web::Query::<BingeQuery>::from_query(http_req.query_string()) {
+        Ok(binge_query) => binge_query.into_inner(),
+        Err(_) => {
+            return Ok(HttpResponse::BadRequest().json(ErrorResponse {
+                error: "Invalid binge parameters".to_string(),
+            }))
+        }
+    };
+    let gap = match binge_query.gap_minutes {
+        Some(minutes @ 1..=1440) => chrono::Duration::minutes(minutes.into()),
+        Some(_) => {
+            return Ok(HttpResponse::BadRequest().json(ErrorResponse {
+                error: "`gap_minutes` must be between 1 and 1440".to_string(),
+            }))
+        }
+        None => stats::sessions::DEFAULT_GAP,
+    };
+    let min_episodes = binge_query
+        .min_episodes
+        .unwrap_or(stats::sessions::DEFAULT_MIN_BINGE_EPISODES)
+        .max(1);
+
+    match load_session_history(&http_req, &query, &source, session, &cache, &store).await {
+        Ok((_, entries, freshness)) => Ok(HttpResponse::Ok().json(StatsResponse {
+            data: stats::binges(&entries, gap, min_episodes),
+            freshness,
+        })),
+        Err(response) => Ok(response),
+    }
+}
+
+async fn get_stats_completion<S: WatchDataSource>(
+    http_req: HttpRequest,
+    query: web::Query<HistoryQuery>,
+    source: web::Data<S>,
+    sessions: web::Data<SessionStore<S::Session>>,
+    cache: web::Data<AppCache>,
+    store: web::Data<HistoryStore>,
+    limiter: web::Data<RateLimiter>,
+) -> Result<HttpResponse> {
+    match load_history::<S>(&http_req, &query, &source, &sessions, &cache, &store, &limiter).await {
+        Ok((session, entries, freshness)) => {
+            let catalogues = History::new(source.get_ref(), &session.upstream)
+                .with_metadata_cache(cache.metadata())
+                .fetch_catalogues(&entries)
+                .await;
+            Ok(HttpResponse::Ok().json(StatsResponse {
+                data: stats::completion(&entries, &catalogues, chrono::Utc::now()),
+                freshness,
+            }))
+        }
+        Err(response) => Ok(response),
+    }
+}
+
+async fn get_stats_rewatches<S: WatchDataSource>(
+    http_req: HttpRequest,
+    query: web::Query<HistoryQuery>,
+    source: web::Data<S>,
+    sessions: web::Data<SessionStore<S::Session>>,
+    cache: web::Data<AppCache>,
+    store: web::Data<HistoryStore>,
+    limiter: web::Data<RateLimiter>,
+) -> Result<HttpResponse> {
+    match load_history::<S>(&http_req, &query, &source, &sessions, &cache, &store, &limiter).await {
+        Ok((_, entries, freshness)) => Ok(HttpResponse::Ok().json(StatsResponse {
+            data: stats::rewatches(&entries),
+            freshness,
+        })),
+        Err(response) => Ok(response),
+    }
+}
+
+/// Authorizes the request and returns the session with the history for the
+/// query's window, see `load_session_history`.
+async fn load_history<S: WatchDataSource>(
+    http_req: &HttpRequest,
+    query: &HistoryQuery,
+    source: &web::Data<S>,
+    sessions: &SessionStore<S::Session>,
+    cache: &web::Data<AppCache>,
+    store: &web::Data<HistoryStore>,
+    limiter: &RateLimiter,
+) -> std::result::Result<(Arc<UserSession<S::Session>>, Vec<HistoryEntry>, Freshness), HttpResponse> {
+    let session = authorize(http_req, sessions, limiter).await?;
+    load_session_history(http_req, query, source, session, cache, store).await
+}
+
+/// The history for the query's window of an authorized session, from the
+/// cache, the store or Crunchyroll, in that order. A stale cached history
+/// is returned as is and refreshed in the background.
+async fn load_session_history<S: WatchDataSource>(
+    http_req: &HttpRequest,
+    query: &HistoryQuery,
+    source: &web::Data<S>,
+    session: Arc<UserSession<S::Session>>,
+    cache: &web::Data<AppCache>,
+    store: &web::Data<HistoryStore>,
+) -> std::result::Result<(Arc<UserSession<S::Session>>, Vec<HistoryEntry>, Freshness), HttpResponse> {
+    let ip = peer_ip(http_req);
+    let (window, completion_threshold) = history_params(ip, query)?;
+
+    if let Some((entries, freshness)) =
+        cached_history(ip, query.force_refresh, &session, cache, store, &window).await
+    {
+        if freshness.stale {
+            refresh_in_background(source, &session, cache, store, window);
+        }
+        return Ok((session, reclassify(entries, completion_threshold), freshness));
+    }
+
+    tracing::info!(ip = %ip, event = "fetch_start");
+    let history = History::new(source.get_ref(), &session.upstream).with_metadata_cache(cache.metadata());
+
+    match fetch_watch_history(&history, &session, cache, store, HISTORY_PAGE_SIZE, &window).await {
         Ok(data) => {
             tracing::info!(ip = %ip, event = "fetch_success", items = data.len());
-            limiter.record_success(ip).await;
-            cache.set_history(cache_key, data.clone()).await;
-            Ok(HttpResponse::Ok().json(HistoryResponse { data }))
+            Ok((session, reclassify(data, completion_threshold), Freshness::default()))
         }
         Err(e) => {
             tracing::error!(ip = %ip, event = "fetch_failed", error = %e);
-            limiter.record_failure(ip).await;
-            Ok(HttpResponse::InternalServerError().json(ErrorResponse {
+            Err(HttpResponse::InternalServerError().json(ErrorResponse {
                 error: "Failed to fetch watch history".to_string(),
             }))
         }
     }
 }
- 
-async fn fetch_watch_history(
-    email: &str,
-    password: &str,
+
+/// Syncs `window` again without anyone waiting on it. Concurrent refreshes
+/// and fetches of the same history share one sync. The refresh is dropped
+/// once the session ends, as it needs the session's Crunchyroll login and
+/// history key.
+fn refresh_in_background<S: WatchDataSource>(
+    source: &web::Data<S>,
+    session: &Arc<UserSession<S::Session>>,
+    cache: &web::Data<AppCache>,
+    store: &web::Data<HistoryStore>,
+    window: HistoryWindow,
+) {
+    let (source, session, cache, store) = (source.clone(), session.clone(), cache.clone(), store.clone());
+    actix_web::rt::spawn(async move {
+        if session.is_ended() {
+            return;
+        }
+        tracing::info!(event = "stale_refresh_start");
+        let history = History::new(source.get_ref(), &session.upstream).with_metadata_cache(cache.metadata());
+        tokio::select! {
+            result = fetch_watch_history(&history, &session, &cache, &store, HISTORY_PAGE_SIZE, &window) => match result {
+                Ok(items) => tracing::info!(event = "stale_refresh_success", items = items.len()),
+                Err(e) => tracing::warn!(event = "stale_refresh_failed", error = %e),
+            },
+            _ = session.ended() => tracing::info!(event = "stale_refresh_cancelled"),
+        }
+    });
+}
+
+/// The window and completion threshold asked for by `query`.
+fn history_params(
+    ip: IpAddr,
+    query: &HistoryQuery,
+) -> std::result::Result<(HistoryWindow, f64), HttpResponse> {
+    let window = match history_window(query) {
+        Ok(window) => window,
+        Err(error) => {
+            tracing::warn!(ip = %ip, event = "invalid_history_window", error = %error);
+            return Err(HttpResponse::BadRequest().json(ErrorResponse {
+                error: error.to_string(),
+            }));
+        }
+    };
+    match completion_threshold(query) {
+        Ok(threshold) => Ok((window, threshold)),
+        Err(error) => Err(HttpResponse::BadRequest().json(ErrorResponse {
+            error: error.to_string(),
+        })),
+    }
+}
+
+/// The history for `window` from the cache, or read through from the
+/// store, which outlives the process cache. `None` when neither has it or
+/// on a forced refresh.
+async fn cached_history<T>(
+    ip: IpAddr,
+    force_refresh: bool,
+    session: &UserSession<T>,
+    cache: &AppCache,
+    store: &HistoryStore,
+    window: &HistoryWindow,
+) -> Option<(Vec<HistoryEntry>, Freshness)> {
+    if force_refresh {
+        tracing::info!(ip = %ip, event = "cache_bypass_forced");
+        return None;
+    }
+
+    let cache_key = AppCache::history_key(&session.user_key, window);
+    if let Some(cached) = cache.get_history(&cache_key, &session.history_key).await {
+        tracing::info!(ip = %ip, event = "cache_hit", items = cached.data.len(), stale = cached.stale);
+        let freshness = Freshness {
+            stale: cached.stale,
+            age_seconds: cached.age.as_secs(),
+        };
+        return Some((cached.data, freshness));
+    }
+
+    match sync::stored_history(
+        store,
+        &session.user_key,
+        &session.history_key,
+        window,
+        cache::HISTORY_TTL,
+    )
+    .await
+    {
+        Ok(Some((stored, age))) => {
+            tracing::info!(ip = %ip, event = "store_hit", items = stored.len());
+            cache.set_synced_history(cache_key, &session.history_key, stored.clone(), age).await;
+            let freshness = Freshness {
+                stale: false,
+                age_seconds: age.as_secs(),
+            };
+            Some((stored, freshness))
+        }
+        Ok(None) => None,
+        Err(e) => {
+            tracing::warn!(ip = %ip, event = "store_read_failed", error = %e);
+            None
+        }
+    }
+}
+
+/// Entries are cached and stored as classified at fetch time, so the
+/// requested threshold is applied on the way out.
+fn reclassify(mut entries: Vec<HistoryEntry>, completion_threshold: f64) -> Vec<HistoryEntry> {
+    for entry in &mut entries {
+        history::classify(entry, completion_threshold);
+    }
+    entries
+}
+
+fn history_window(query: &HistoryQuery) -> std::result::Result<HistoryWindow, &'static str> {
+    let since = match (query.all, query.since) {
+        (true, Some(_)) => return Err("`all` cannot be combined with `since`"),
+        (true, None) => Since::All,
+        (false, Some(since)) => Since::At(since),
+        (false, None) => HistoryWindow::default().since,
+    };
+    if let (Since::At(since), Some(until)) = (since, query.until) {
+        if since > until {
+            return Err("`since` must not be after `until`");
+        }
+    }
+    Ok(HistoryWindow {
+        since,
+        until: query.until,
+    })
+}
+
+fn completion_threshold(query: &HistoryQuery) -> std::result::Result<f64, &'static str> {
+    match query.completion_threshold {
+        Some(threshold) if threshold > 0.0 && threshold <= 1.0 => Ok(threshold),
+        Some(_) => Err("`completion_threshold` must be above 0 and at most 1"),
+        None => Ok(history::DEFAULT_COMPLETION_THRESHOLD),
+    }
+}
+
+/// Syncs the history for `window` and caches the result. Concurrent calls
+/// for the same user and window share one sync.
+async fn fetch_watch_history<S: WatchDataSource>(
+    history: &History<'_, S>,
+    session: &UserSession<S::Session>,
+    cache: &AppCache,
+    store: &HistoryStore,
     limit: Option<usize>,
-) -> anyhow::Result<Vec<models::HistoryEntry>> {
-    let client = CrunchyrollClient::new(email, password).await?;
-    let history = history::History::new(&client);
-    let items = history.fetch_history(limit).await?;
-    tracing::info!(event = "history_retrieved", items = items.len());
-    Ok(items)
+    window: &HistoryWindow,
+) -> anyhow::Result<Vec<HistoryEntry>> {
+    let cache_key = AppCache::history_key(&session.user_key, window);
+    cache
+        .single_flight(&cache_key, async {
+            let items = sync::sync_history(
+                history,
+                store,
+                &session.user_key,
+                &session.history_key,
+                limit,
+                window,
+            )
+            .await?;
+            tracing::info!(event = "history_retrieved", items = items.len());
+            let metadata = cache.metadata().stats().await;
+            tracing::debug!(
+                event = "metadata_cache",
+                entries = metadata.entries,
+                hits = metadata.hits,
+                misses = metadata.misses
+            );
+            cache.set_history(cache_key.clone(), &session.history_key, items.clone()).await;
+            Ok(items)
+        })
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{http::StatusCode, test};
+    use crunchyroll_rs::MediaCollection;
+    use source::fixture::{self, FixtureSource};
+    use source::mock_server::{self, MockCrunchyroll};
+    use std::sync::Arc;
+    use store::StoredHistory;
+
+    const EMAIL: &str = "user@example.com";
+    const PASSWORD: &str = "hunter22";
+    const ADMIN_TOKEN: &str = "admin-token-admin-token-admin-token";
+
+    fn fixture_source() -> FixtureSource {
+        FixtureSource::new()
+            .with_account(EMAIL, PASSWORD)
+            .with_entry(fixture::played(
+                MediaCollection::Episode(fixture::episode("ep-1", "series-1", "Frieren", "Ep 1")),
+                chrono::Utc::now(),
+                600,
+            ))
+            .with_series(fixture::series("series-1", &["fantasy"]))
+    }
+
+    fn login_body(email: &str, password: &str) -> serde_json::Value {
+        serde_json::json!({ "email": email, "password": password })
+    }
+
+    macro_rules! init_app {
+        ($source_ty:ty, $source:expr, $cache:expr) => {
+            test::init_service(
+                App::new()
+                    .app_data(web::Data::<$source_ty>::from($source))
+                    .app_data(web::Data::from($cache.clone()))
+                    .app_data(web::Data::from(SessionStore::<
+                        <$source_ty as WatchDataSource>::Session,
+                    >::new()))
+                    .app_data(web::Data::from(RateLimiter::new()))
+                    .app_data(web::Data::from(HistoryStore::new()))
+                    .app_data(web::Data::new(AdminToken::new(Some(ADMIN_TOKEN)).unwrap()))
+                    .configure(routes::<$source_ty>),
+            )
+            .await
+        };
+    }
+
+    /// Logs in through `/api/auth` and returns the session token.
+    macro_rules! login {
+        ($app:expr, $email:expr, $password:expr) => {{
+            let req = test::TestRequest::post()
+                .uri("/api/auth")
+                .set_json(login_body($email, $password))
+                .to_request();
+            let body: serde_json::Value = test::call_and_read_body_json(&$app, req).await;
+            body["token"].as_str().expect("login returned no token").to_string()
+        }};
+    }
+
+    /// Logs in through `/api/auth` and returns the full response body.
+    macro_rules! login_response {
+        ($app:expr, $email:expr, $password:expr) => {{
+            let req = test::TestRequest::post()
+                .uri("/api/auth")
+                .set_json(login_body($email, $password))
+                .to_request();
+            let body: serde_json::Value = test::call_and_read_body_json(&$app, req).await;
+            body
+        }};
+    }
+
+    /// The key a login with `EMAIL` and `PASSWORD` seals history under.
+    fn sealing_key() -> HistoryKey {
+        HistoryKey::derive(PASSWORD, &AppCache::cache_key(EMAIL)).unwrap()
+    }
+
+    fn bearer(token: &str) -> (header::HeaderName, String) {
+        (header::AUTHORIZATION, format!("Bearer {}", token))
+    }
+
+    fn history_request(token: &str) -> test::TestRequest {
+        test::TestRequest::get()
+            .uri("/api/watch-history")
+            .insert_header(bearer(token))
+    }
+
+    #[actix_web::test]
+    async fn auth_returns_session_token() {
+        let app = init_app!(FixtureSource, Arc::new(fixture_source()), AppCache::new());
+
+        let req = test::TestRequest::post()
+            .uri("/api/auth")
+            .set_json(login_body(EMAIL, PASSWORD))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["success"], true);
+        assert_eq!(body["token"].as_str().unwrap().len(), 64);
+    }
+
+    #[actix_web::test]
+    async fn auth_rejects_invalid_credentials() {
+        let app = init_app!(FixtureSource, Arc::new(fixture_source()), AppCache::new());
+
+        let req = test::TestRequest::post()
+            .uri("/api/auth")
+            .set_json(login_body(EMAIL, "wrong"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_web::test]
+    async fn watch_history_returns_and_caches_entries() {
+        let cache = AppCache::new();
+        let app = init_app!(FixtureSource, Arc::new(fixture_source()), cache);
+        let token = login!(app, EMAIL, PASSWORD);
+
+        let body: serde_json::Value =
+            test::call_and_read_body_json(&app, history_request(&token).to_request()).await;
+
+        assert_eq!(body["data"].as_array().unwrap().len(), 1);
+        assert_eq!(body["data"][0]["title"], "Frieren");
+        assert_eq!(body["data"][0]["genres"][0], "fantasy");
+
+        let cache_key = AppCache::history_key(&AppCache::cache_key(EMAIL), &HistoryWindow::default());
+        let cached = cache.get_history(&cache_key, &sealing_key()).await;
+        assert_eq!(cached.map(|cached| cached.data.len()), Some(1));
+        assert_eq!(body["stale"], false);
+    }
+
+    #[actix_web::test]
+    async fn stale_history_is_served_and_refreshed() {
+        let cache = AppCache::new();
+        let app = init_app!(FixtureSource, Arc::new(fixture_source()), cache);
+        let token = login!(app, EMAIL, PASSWORD);
+        let cache_key = AppCache::history_key(&AppCache::cache_key(EMAIL), &HistoryWindow::default());
+        cache.set_history_expired(cache_key.clone(), &sealing_key(), vec![]).await;
+
+        let body: serde_json::Value =
+            test::call_and_read_body_json(&app, history_request(&token).to_request()).await;
+
+        assert_eq!(body["stale"], true);
+        assert!(body["age_seconds"].is_u64());
+        assert!(body["data"].as_array().unwrap().is_empty());
+
+        let mut refreshed = None;
+        for _ in 0..100 {
+            refreshed = cache.get_history(&cache_key, &sealing_key()).await.filter(|cached| !cached.stale);
+            if refreshed.is_some() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert_eq!(refreshed.map(|cached| cached.data.len()), Some(1));
+
+        let body: serde_json::Value =
+            test::call_and_read_body_json(&app, history_request(&token).to_request()).await;
+        assert_eq!(body["stale"], false);
+        assert_eq!(body["data"].as_array().unwrap().len(), 1);
+    }
+
+    #[actix_web::test]
+    async fn store_read_through_is_as_old_as_the_sync() {
+        let (cache, store) = (AppCache::new(), HistoryStore::new());
+        let (_, session) = SessionStore::new()
+            .create(AppCache::cache_key(EMAIL), sealing_key(), EMAIL.to_string())
+            .await;
+        let played = (chrono::Utc::now() - chrono::Duration::days(1)).to_rfc3339();
+        let stored = StoredHistory {
+            entries: vec![HistoryEntry::test("item-0", Some(&played))],
+            synced_at: chrono::Utc::now() - chrono::Duration::minutes(10),
+            ..Default::default()
+        };
+        store.put(session.user_key.clone(), &session.history_key, stored).await.unwrap();
+        let ip: IpAddr = "127.0.0.1".parse().unwrap();
+        let window = HistoryWindow::default();
+
+        let (entries, freshness) = cached_history(ip, false, &session, &cache, &store, &window).await.unwrap();
+        assert_eq!(entries.len(), 1);
+        assert!(!freshness.stale);
+        assert!((600..610).contains(&freshness.age_seconds));
+
+        // Now from the cache, which counts from the sync too.
+        let (_, freshness) = cached_history(ip, false, &session, &cache, &store, &window).await.unwrap();
+        assert!((600..610).contains(&freshness.age_seconds));
+    }
+
+    #[actix_web::test]
+    async fn refresh_is_skipped_once_the_session_ends() {
+        let (cache, store) = (AppCache::new(), HistoryStore::new());
+        let sessions = SessionStore::new();
+        let (token, session) = sessions
+            .create(AppCache::cache_key(EMAIL), sealing_key(), EMAIL.to_string())
+            .await;
+        sessions.revoke(&token).await;
+        let window = HistoryWindow::default();
+
+        refresh_in_background(
+            &web::Data::new(fixture_source()),
+            &session,
+            &web::Data::from(cache.clone()),
+            &web::Data::from(store.clone()),
+            window,
+        );
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+
+        let cache_key = AppCache::history_key(&session.user_key, &window);
+        assert!(cache.get_history(&cache_key, &sealing_key()).await.is_none());
+        assert!(store.get(&session.user_key, &sealing_key()).await.unwrap().is_none());
+    }
+
+    #[actix_web::test]
+    async fn watch_history_requires_token() {
+        let app = init_app!(FixtureSource, Arc::new(fixture_source()), AppCache::new());
+
+        let req = test::TestRequest::get().uri("/api/watch-history").to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_web::test]
+    async fn watch_history_rejects_unknown_token() {
+        let app = init_app!(FixtureSource, Arc::new(fixture_source()), AppCache::new());
+
+        let resp = test::call_service(&app, history_request("not-a-token").to_request()).await;
+
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_web::test]
+    async fn watch_history_windows_are_cached_separately() {
+        let source = fixture_source().with_entry(fixture::played(
+            MediaCollection::Episode(fixture::episode("ep-0", "series-1", "Frieren", "Ep 0")),
+            chrono::Utc::now() - chrono::Duration::days(800),
+            0,
+        ));
+        let app = init_app!(FixtureSource, Arc::new(source), AppCache::new());
+        let token = login!(app, EMAIL, PASSWORD);
+
+        let body: serde_json::Value =
+            test::call_and_read_body_json(&app, history_request(&token).to_request()).await;
+        assert_eq!(body["data"].as_array().unwrap().len(), 1);
+
+        let req = test::TestRequest::get()
+            .uri("/api/watch-history?all=true")
+            .insert_header(bearer(&token))
+            .to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["data"].as_array().unwrap().len(), 2);
+    }
+
+    #[actix_web::test]
+    async fn watch_history_rejects_invalid_window() {
+        let app = init_app!(FixtureSource, Arc::new(fixture_source()), AppCache::new());
+        let token = login!(app, EMAIL, PASSWORD);
+
+        for uri in [
+            "/api/watch-history?all=true&since=2024-01-01T00:00:00Z",
+            "/api/watch-history?since=2024-02-01T00:00:00Z&until=2024-01-01T00:00:00Z",
+        ] {
+            let req = test::TestRequest::get()
+                .uri(uri)
+                .insert_header(bearer(&token))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), StatusCode::BAD_REQUEST, "{}", uri);
+        }
+    }
+
+    fn ndjson_events(body: &[u8]) -> Vec<serde_json::Value> {
+        std::str::from_utf8(body)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[actix_web::test]
+    async fn watch_history_stream_sends_pages_progress_and_done() {
+        let source = fixture_source().with_entry(fixture::played(
+            MediaCollection::Movie(fixture::movie("movie-1", "listing-1", "Suzume")),
+            chrono::Utc::now() - chrono::Duration::days(100),
+            3300,
+        ));
+        let app = init_app!(FixtureSource, Arc::new(source), AppCache::new());
+        let token = login!(app, EMAIL, PASSWORD);
+        let stream_request = || {
+            test::TestRequest::get()
+                .uri("/api/watch-history/stream")
+                .insert_header(bearer(&token))
+                .to_request()
+        };
+
+        let resp = test::call_service(&app, stream_request()).await;
+        assert_eq!(resp.headers().get(header::CONTENT_TYPE).unwrap(), "application/x-ndjson");
+        let events = ndjson_events(&test::read_body(resp).await);
+
+        let types: Vec<_> = events.iter().map(|event| event["type"].as_str().unwrap()).collect();
+        assert_eq!(types, vec!["entries", "progress", "done"]);
+        assert_eq!(events[0]["entries"].as_array().unwrap().len(), 2);
+        assert_eq!(events[1]["fetched"], 2);
+        let fraction = events[1]["fraction"].as_f64().unwrap();
+        assert!(fraction > 0.25 && fraction < 0.3, "{}", fraction);
+        assert_eq!(events[2]["items"], 2);
+
+        // Served from the cache in one go.
+        let events = ndjson_events(&test::call_and_read_body(&app, stream_request()).await);
+        let types: Vec<_> = events.iter().map(|event| event["type"].as_str().unwrap()).collect();
+        assert_eq!(types, vec!["entries", "done"]);
+        assert_eq!(events[0]["entries"].as_array().unwrap().len(), 2);
+    }
+
+    #[actix_web::test]
+    async fn watch_history_stream_speaks_sse_when_asked() {
+        let app = init_app!(FixtureSource, Arc::new(fixture_source()), AppCache::new());
+        let token = login!(app, EMAIL, PASSWORD);
+
+        let req = test::TestRequest::get()
+            .uri("/api/watch-history/stream")
+            .insert_header(bearer(&token))
+            .insert_header((header::ACCEPT, "text/event-stream"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.headers().get(header::CONTENT_TYPE).unwrap(), "text/event-stream");
+        let body = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+        assert!(body.starts_with("event: entries\ndata: {\"type\":\"entries\""), "{}", body);
+        assert!(
+            body.ends_with("event: done\ndata: {\"type\":\"done\",\"items\":1,\"stale\":false,\"age_seconds\":0}\n\n"),
+            "{}",
+            body
+        );
+    }
+
+    #[actix_web::test]
+    async fn watch_history_stream_requires_a_session() {
+        let app = init_app!(FixtureSource, Arc::new(fixture_source()), AppCache::new());
+
+        let req = test::TestRequest::get().uri("/api/watch-history/stream").to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_web::test]
+    async fn stats_summary_is_computed_from_history() {
+        let source = fixture_source().with_entry(fixture::played(
+            MediaCollection::Movie(fixture::movie("movie-1", "listing-1", "Suzume")),
+            chrono::Utc::now() - chrono::Duration::hours(1),
+            3300,
+        ));
+        let app = init_app!(FixtureSource, Arc::new(source), AppCache::new());
+        let token = login!(app, EMAIL, PASSWORD);
+
+        let req = test::TestRequest::get()
+            .uri("/api/stats/summary")
+            .insert_header(bearer(&token))
+            .to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+
+        assert_eq!(body["data"]["titles"], 2);
+        assert_eq!(body["data"]["series"], 1);
+        assert_eq!(body["data"]["episodes"], 1);
+        assert_eq!(body["data"]["movies"], 1);
+        assert_eq!(body["data"]["watched_ms"], 600_000 + 3_300_000);
+    }
+
+    #[actix_web::test]
+    async fn stats_rewatches_split_watch_time() {
+        let app = init_app!(FixtureSource, Arc::new(fixture_source()), AppCache::new());
+        let token = login!(app, EMAIL, PASSWORD);
+
+        let req = test::TestRequest::get()
+            .uri("/api/stats/rewatches")
+            .insert_header(bearer(&token))
+            .to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+
+        assert_eq!(body["data"]["new_entries"], 1);
+        assert_eq!(body["data"]["new_ms"], 600_000);
+        assert_eq!(body["data"]["rewatched_entries"], 0);
+    }
+
+    #[actix_web::test]
+    async fn watch_status_follows_requested_completion_threshold() {
+        let app = init_app!(FixtureSource, Arc::new(fixture_source()), AppCache::new());
+        let token = login!(app, EMAIL, PASSWORD);
+
+        // The fixture play covers 10 of 24 minutes.
+        let default: serde_json::Value =
+            test::call_and_read_body_json(&app, history_request(&token).to_request()).await;
+        let lenient: serde_json::Value = test::call_and_read_body_json(
+            &app,
+            history_request(&token)
+                .uri("/api/watch-history?completion_threshold=0.4")
+                .to_request(),
+        )
+        .await;
+
+        assert_eq!(default["data"][0]["watched_ms"], 600_000);
+        assert_eq!(default["data"][0]["watch_status"], "partial");
+        assert_eq!(lenient["data"][0]["watch_status"], "completed");
+    }
+
+    #[actix_web::test]
+    async fn watch_history_rejects_invalid_completion_threshold() {
+        let app = init_app!(FixtureSource, Arc::new(fixture_source()), AppCache::new());
+        let token = login!(app, EMAIL, PASSWORD);
+
+        for threshold in ["0", "1.5", "-1"] {
+            let req = history_request(&token)
+                .uri(&format!("/api/watch-history?completion_threshold={}", threshold))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        }
+    }
+
+    #[actix_web::test]
+    async fn stats_summary_requires_token() {
+        let app = init_app!(FixtureSource, Arc::new(fixture_source()), AppCache::new());
+
+        let req = test::TestRequest::get().uri("/api/stats/summary").to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_web::test]
+    async fn stats_streaks_use_requested_timezone() {
+        let app = init_app!(FixtureSource, Arc::new(fixture_source()), AppCache::new());
+        let token = login!(app, EMAIL, PASSWORD);
+
+        let req = test::TestRequest::get()
+            .uri("/api/stats/streaks?tz=Asia/Tokyo")
+            .insert_header(bearer(&token))
+            .to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+
+        assert_eq!(body["data"]["timezone"], "Asia/Tokyo");
+        assert_eq!(body["data"]["current_streak"]["days"], 1);
+        assert_eq!(body["data"]["peak_day"]["watched_ms"], 600_000);
+    }
+
+    #[actix_web::test]
+    async fn stats_streaks_reject_unknown_timezone() {
+        let app = init_app!(FixtureSource, Arc::new(fixture_source()), AppCache::new());
+        let token = login!(app, EMAIL, PASSWORD);
+
+        let req = test::TestRequest::get()
+            .uri("/api/stats/streaks?tz=Mars/Olympus_Mons")
+            .insert_header(bearer(&token))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn stats_streaks_authorize_before_checking_the_timezone() {
+        let app = init_app!(FixtureSource, Arc::new(fixture_source()), AppCache::new());
+
+        let req = test::TestRequest::get().uri("/api/stats/streaks?tz=Mars/Olympus_Mons").to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_web::test]
+    async fn stats_binges_groups_back_to_back_episodes() {
+        let now = chrono::Utc::now();
+        let source = (2..=3).fold(fixture_source(), |source, number| {
+            let id = format!("ep-{}", number);
+            source.with_entry(fixture::played(
+                MediaCollection::Episode(fixture::episode(&id, "series-1", "Frieren", &id)),
+                now - chrono::Duration::minutes(25 * number),
+                1440,
+            ))
+        });
+        let app = init_app!(FixtureSource, Arc::new(source), AppCache::new());
+        let token = login!(app, EMAIL, PASSWORD);
+
+        let req = test::TestRequest::get()
+            .uri("/api/stats/binges?min_episodes=2")
+            .insert_header(bearer(&token))
+            .to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+
+        assert_eq!(body["data"].as_array().unwrap().len(), 1);
+        assert_eq!(body["data"][0]["series_id"], "series-1");
+        assert_eq!(body["data"][0]["episodes"], 3);
+    }
+
+    #[actix_web::test]
+    async fn stats_binges_reject_invalid_gap() {
+        let app = init_app!(FixtureSource, Arc::new(fixture_source()), AppCache::new());
+        let token = login!(app, EMAIL, PASSWORD);
+
+        let req = test::TestRequest::get()
+            .uri("/api/stats/binges?gap_minutes=0")
+            .insert_header(bearer(&token))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn stats_binges_authorize_before_checking_parameters() {
+        let app = init_app!(FixtureSource, Arc::new(fixture_source()), AppCache::new());
+
+        let req = test::TestRequest::get().uri("/api/stats/binges?gap_minutes=0").to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_web::test]
+    async fn stats_completion_compares_history_with_seasons() {
+        let source = fixture_source().with_season(
+            fixture::season("season-1", "series-1", 1),
+            vec![
+                fixture::episode("ep-1", "series-1", "Frieren", "Ep 1"),
+                fixture::episode("ep-2", "series-1", "Frieren", "Ep 2"),
+            ],
+        );
+        let app = init_app!(FixtureSource, Arc::new(source), AppCache::new());
+        let token = login!(app, EMAIL, PASSWORD);
+
+        let req = test::TestRequest::get()
+            .uri("/api/stats/completion")
+            .insert_header(bearer(&token))
+            .to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+
+        // The fixture play stops at 10 of 24 minutes, so nothing is watched yet.
+        let series = &body["data"][0];
+        assert_eq!(series["series_id"], "series-1");
+        assert_eq!(series["watched"], 0);
+        assert_eq!(series["available"], 2);
+        assert_eq!(series["status"], "in_progress");
+        assert_eq!(series["seasons"][0]["season_id"], "season-1");
+    }
+
+    #[actix_web::test]
+    async fn logout_revokes_token() {
+        let app = init_app!(FixtureSource, Arc::new(fixture_source()), AppCache::new());
+        let token = login!(app, EMAIL, PASSWORD);
+
+        let req = test::TestRequest::post()
+            .uri("/api/logout")
+            .insert_header(bearer(&token))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let resp = test::call_service(&app, history_request(&token).to_request()).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_web::test]
+    async fn logout_without_token_is_unauthorized() {
+        let app = init_app!(FixtureSource, Arc::new(fixture_source()), AppCache::new());
+
+        let req = test::TestRequest::post().uri("/api/logout").to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_web::test]
+    async fn revoke_session_by_id_from_another_session() {
+        let app = init_app!(FixtureSource, Arc::new(fixture_source()), AppCache::new());
+        let shared_machine = login_response!(app, EMAIL, PASSWORD);
+        let own_device = login!(app, EMAIL, PASSWORD);
+
+        let req = test::TestRequest::delete()
+            .uri(&format!("/api/sessions/{}", shared_machine["session_id"].as_str().unwrap()))
+            .insert_header(bearer(&own_device))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let revoked = shared_machine["token"].as_str().unwrap();
+        let resp = test::call_service(&app, history_request(revoked).to_request()).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        let resp = test::call_service(&app, history_request(&own_device).to_request()).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn list_sessions_shows_the_callers_sessions() {
+        let source = fixture_source().with_account("other@example.com", PASSWORD);
+        let app = init_app!(FixtureSource, Arc::new(source), AppCache::new());
+        let other_device = login_response!(app, EMAIL, PASSWORD);
+        let own_device = login_response!(app, EMAIL, PASSWORD);
+        login!(app, "other@example.com", PASSWORD);
+
+        let req = test::TestRequest::get()
+            .uri("/api/sessions")
+            .insert_header(bearer(own_device["token"].as_str().unwrap()))
+            .to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+
+        let sessions = body["sessions"].as_array().unwrap();
+        assert_eq!(sessions.len(), 2);
+        let current: Vec<_> = sessions
+            .iter()
+            .map(|session| (session["id"].clone(), session["current"].clone()))
+            .collect();
+        assert!(current.contains(&(other_device["session_id"].clone(), false.into())));
+        assert!(current.contains(&(own_device["session_id"].clone(), true.into())));
+        assert!(sessions.iter().all(|session| session["created_at"].is_string()
+            && session["last_used_at"].is_string()
+            && session.get("token").is_none()));
+    }
+
+    #[actix_web::test]
+    async fn list_sessions_requires_a_session() {
+        let app = init_app!(FixtureSource, Arc::new(fixture_source()), AppCache::new());
+
+        let req = test::TestRequest::get().uri("/api/sessions").to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_web::test]
+    async fn revoke_session_of_other_user_is_not_found() {
+        let source = fixture_source().with_account("other@example.com", PASSWORD);
+        let app = init_app!(FixtureSource, Arc::new(source), AppCache::new());
+        let victim = login_response!(app, EMAIL, PASSWORD);
+        let attacker = login!(app, "other@example.com", PASSWORD);
+
+        let req = test::TestRequest::delete()
+            .uri(&format!("/api/sessions/{}", victim["session_id"].as_str().unwrap()))
+            .insert_header(bearer(&attacker))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        let token = victim["token"].as_str().unwrap();
+        let resp = test::call_service(&app, history_request(token).to_request()).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn mock_upstream_auth_accepts_valid_credentials() {
+        let mock = MockCrunchyroll::start().await;
+        let source = Arc::new(CrunchyrollSource::with_http_client(mock.http_client()));
+        let app = init_app!(CrunchyrollSource, source, AppCache::new());
+
+        let token = login!(app, mock_server::EMAIL, mock_server::PASSWORD);
+
+        assert!(!token.is_empty());
+        assert!(mock.requests().contains(&"/auth/v1/token".to_string()));
+    }
+
+    #[actix_web::test]
+    async fn mock_upstream_auth_rejects_invalid_credentials() {
+        let mock = MockCrunchyroll::start().await;
+        let source = Arc::new(CrunchyrollSource::with_http_client(mock.http_client()));
+        let app = init_app!(CrunchyrollSource, source, AppCache::new());
+
+        let req = test::TestRequest::post()
+            .uri("/api/auth")
+            .set_json(login_body(mock_server::EMAIL, "wrong"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_web::test]
+    async fn mock_upstream_watch_history_end_to_end() {
+        let mock = MockCrunchyroll::start().await;
+        let source = Arc::new(CrunchyrollSource::with_http_client(mock.http_client()));
+        let app = init_app!(CrunchyrollSource, source, AppCache::new());
+        let token = login!(app, mock_server::EMAIL, mock_server::PASSWORD);
+
+        let body: serde_json::Value =
+            test::call_and_read_body_json(&app, history_request(&token).to_request()).await;
+        let data = body["data"].as_array().unwrap();
+
+        // The unresolvable entry without a panel is dropped.
+        assert_eq!(data.len(), 4);
+
+        assert_eq!(data[0]["content_id"], "GJWU2VKK3");
+        assert_eq!(data[0]["title"], "Frieren: Beyond Journey's End");
+        assert_eq!(data[0]["genres"], serde_json::json!(["action", "adventure", "fantasy"]));
+        assert_eq!(data[0]["images"].as_array().unwrap().len(), 2);
+        assert_eq!(data[0]["episode_metadata"]["episode_number"], 2);
+        assert_eq!(data[0]["episode_metadata"]["audio_locale"], "ja-JP");
+        assert_eq!(data[0]["episode_metadata"]["subtitle_locales"][0], "en-US");
+        assert_eq!(data[0]["episode_metadata"]["is_subbed"], true);
+        assert_eq!(data[0]["episode_metadata"]["air_date"], "2023-09-29T15:00:00Z");
+
+        // Missing panel resolved through the episode endpoint.
+        assert_eq!(data[1]["content_id"], "GRDQCVP9Y");
+        assert_eq!(data[1]["episode_title"], "The Journey's End");
+        assert_eq!(data[1]["duration_ms"], 1474000);
+
+        assert_eq!(data[2]["media_type"], "movie");
+        assert_eq!(data[2]["genres"], serde_json::json!(["adventure", "drama", "fantasy"]));
+
+        // Series lookup fails upstream, so no genres.
+        assert_eq!(data[3]["series_id"], "G4PH0WXVJ");
+        assert_eq!(data[3]["genres"], serde_json::json!([]));
+
+        let requests = mock.requests();
+        let series_lookups = requests
+            .iter()
+            .filter(|path| path.as_str() == "/content/v2/cms/series/GG5H5XQX4")
+            .count();
+        assert_eq!(series_lookups, 1);
+        assert!(requests.contains(&"/content/v2/cms/episodes/GVWU0XQ8Z".to_string()));
+        assert!(requests.contains(&"/content/v2/cms/episodes/GXJHM3N00".to_string()));
+
+        // The data call reuses the session instead of logging in again.
+        let logins = requests.iter().filter(|path| path.as_str() == "/auth/v1/token").count();
+        assert_eq!(logins, 1);
+    }
+
+    #[actix_web::test]
+    async fn mock_upstream_completion_end_to_end() {
+        let mock = MockCrunchyroll::start().await;
+        let source = Arc::new(CrunchyrollSource::with_http_client(mock.http_client()));
+        let app = init_app!(CrunchyrollSource, source, AppCache::new());
+        let token = login!(app, mock_server::EMAIL, mock_server::PASSWORD);
+
+        let req = test::TestRequest::get()
+            .uri("/api/stats/completion")
+            .insert_header(bearer(&token))
+            .to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        let data = body["data"].as_array().unwrap();
+
+        // Only Frieren has recorded seasons; the other lookups fail upstream.
+        assert_eq!(data.len(), 1);
+        assert_eq!(data[0]["series_id"], "GG5H5XQX4");
+        assert_eq!(data[0]["watched"], 2);
+        assert_eq!(data[0]["available"], 3);
+        assert_eq!(data[0]["status"], "in_progress");
+
+        // The English dub is a version of season 1, so its episodes are not fetched.
+        let requests = mock.requests();
+        assert!(requests.contains(&"/content/v2/cms/seasons/GY8VEQ95Y/episodes".to_string()));
+        assert!(!requests.iter().any(|path| path.contains("GYQ4MKDZ6")));
+    }
+
+    fn admin_request(method: actix_web::http::Method, uri: &str) -> test::TestRequest {
+        test::TestRequest::default()
+            .method(method)
+            .uri(uri)
+            .insert_header(bearer(ADMIN_TOKEN))
+    }
+
+    #[actix_web::test]
+    async fn admin_cache_report_shows_entries_and_hit_ratio() {
+        let cache = AppCache::new();
+        let app = init_app!(FixtureSource, Arc::new(fixture_source()), cache);
+        let token = login!(app, EMAIL, PASSWORD);
+        test::call_service(&app, history_request(&token).to_request()).await;
+        test::call_service(&app, history_request(&token).to_request()).await;
+
+        let req = admin_request(actix_web::http::Method::GET, "/api/admin/cache").to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+
+        let history = &body["history"];
+        assert_eq!(history["entries"], 1);
+        assert_eq!(history["hit_ratio"], 0.5);
+        assert_eq!(history["max_entries"], 1000);
+        let key = AppCache::history_key(&AppCache::cache_key(EMAIL), &HistoryWindow::default());
+        assert_eq!(history["cached"][0]["key"], key);
+        assert_eq!(history["cached"][0]["items"], 1);
+        assert_eq!(history["cached"][0]["stale"], false);
+        assert_eq!(body["metadata"]["entries"], 1);
+    }
+
+    #[actix_web::test]
+    async fn admin_invalidates_one_users_cache() {
+        let cache = AppCache::new();
+        let app = init_app!(FixtureSource, Arc::new(fixture_source()), cache);
+        let user_key = AppCache::cache_key(EMAIL);
+        let other = AppCache::history_key(&AppCache::cache_key("other@example.com"), &HistoryWindow::default());
+        cache.set_history(AppCache::history_key(&user_key, &HistoryWindow::default()), &sealing_key(), vec![]).await;
+        cache.set_history(other.clone(), &sealing_key(), vec![]).await;
+
+        let uri = format!("/api/admin/cache/users/{}", user_key.to_uppercase());
+        let req = admin_request(actix_web::http::Method::DELETE, &uri).to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+
+        assert_eq!(body["removed"], 1);
+        let keys: Vec<_> = cache.history_entries().await.unwrap().into_iter().map(|e| e.key).collect();
+        assert_eq!(keys, vec![other]);
+    }
+
+    #[actix_web::test]
+    async fn admin_rejects_malformed_user_key() {
+        let cache = AppCache::new();
+        let app = init_app!(FixtureSource, Arc::new(fixture_source()), cache);
+
+        let req = admin_request(actix_web::http::Method::DELETE, "/api/admin/cache/users/user@example.com")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn admin_flushes_the_cache() {
+        let cache = AppCache::new();
+        let app = init_app!(FixtureSource, Arc::new(fixture_source()), cache);
+        cache.set_history("key1".to_string(), &sealing_key(), vec![]).await;
+        cache.set_history("key2".to_string(), &sealing_key(), vec![]).await;
+
+        let req = admin_request(actix_web::http::Method::DELETE, "/api/admin/cache").to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+
+        assert_eq!(body["removed"], 2);
+        assert_eq!(cache.history_stats().await.unwrap().entries, 0);
+    }
+
+    #[actix_web::test]
+    async fn admin_routes_require_the_admin_token() {
+        let cache = AppCache::new();
+        let app = init_app!(FixtureSource, Arc::new(fixture_source()), cache);
+        let token = login!(app, EMAIL, PASSWORD);
+        cache.set_history("key1".to_string(), &sealing_key(), vec![]).await;
+
+        for auth in [None, Some(token.as_str()), Some("wrong")] {
+            let mut req = test::TestRequest::delete().uri("/api/admin/cache");
+            if let Some(auth) = auth {
+                req = req.insert_header(bearer(auth));
+            }
+            let resp = test::call_service(&app, req.to_request()).await;
+            assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        }
+        assert_eq!(cache.history_stats().await.unwrap().entries, 1);
+    }
+
+    #[actix_web::test]
+    async fn admin_routes_are_disabled_without_a_token() {
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(AdminToken::new(None).unwrap()))
+                .app_data(web::Data::from(AppCache::new()))
+                .app_data(web::Data::from(RateLimiter::new()))
+                .route("/api/admin/cache", web::get().to(get_cache_report)),
+        )
+        .await;
+
+        let req = admin_request(actix_web::http::Method::GET, "/api/admin/cache").to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
 }
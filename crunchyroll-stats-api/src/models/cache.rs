@@ -0,0 +1,73 @@
+use serde::Serialize;
+
+/// Body of `GET /api/admin/cache`.
+#[derive(Debug, Serialize)]
+pub struct CacheReport {
+    pub history: HistoryCacheReport,
+    pub metadata: MetadataCacheReport,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HistoryCacheReport {
+    pub entries: usize,
+    /// Approximate, as the histories' JSON size.
+    pub bytes: usize,
+    /// `None` when the backend enforces its own limits.
+    pub max_entries: Option<usize>,
+    pub max_bytes: Option<usize>,
+    pub hits: u64,
+    pub misses: u64,
+    /// `None` until the first lookup.
+    pub hit_ratio: Option<f64>,
+    pub evictions: u64,
+    pub expirations: u64,
+    /// Oldest first.
+    pub cached: Vec<CachedHistoryReport>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CachedHistoryReport {
+    /// The user's hashed key and the window, as `{user_key}:{window}`.
+    pub key: String,
+    pub age_seconds: u64,
+    pub stale: bool,
+    pub items: usize,
+    pub bytes: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MetadataCacheReport {
+    /// Series and movie listings.
+    pub entries: usize,
+    /// Series season and episode lists.
+    pub catalogues: usize,
+    /// Approximate, as the entries' JSON size.
+    pub bytes: usize,
+    pub evictions: u64,
+    pub hits: u64,
+    pub misses: u64,
+    pub hit_ratio: Option<f64>,
+}
+
+/// Body of the admin routes that drop cached histories.
+#[derive(Debug, Serialize)]
+pub struct CacheInvalidation {
+    pub removed: usize,
+}
+
+pub fn hit_ratio(hits: u64, misses: u64) -> Option<f64> {
+    let lookups = hits + misses;
+    (lookups > 0).then(|| hits as f64 / lookups as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hit_ratio_is_none_without_lookups() {
+        assert_eq!(hit_ratio(0, 0), None);
+        assert_eq!(hit_ratio(3, 1), Some(0.75));
+        assert_eq!(hit_ratio(0, 2), Some(0.0));
+    }
+}
@@ -1,15 +1,50 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
+use super::{Genre, MediaKind};
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Image {
     pub source: String,
     pub width: u32,
 }
 
+/// How much of an entry a play covered, see `history::classify`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WatchStatus {
+    /// Played up to the completion threshold.
+    Completed,
+    /// Played for a while but stopped before the threshold.
+    Partial,
+    /// Only peeked at.
+    #[default]
+    Sampled,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EpisodeMetadata {
+    pub season_number: u32,
+    pub season_title: String,
+    /// Missing for specials, which only have a `sequence_number`.
+    pub episode_number: Option<u32>,
+    /// Position in the season; fractional for specials such as 0.5.
+    pub sequence_number: f32,
+    /// Locale codes such as `ja-JP`.
+    pub audio_locale: String,
+    pub subtitle_locales: Vec<String>,
+    pub is_dubbed: bool,
+    pub is_subbed: bool,
+    pub maturity_ratings: Vec<String>,
+    /// When the episode first aired.
+    pub air_date: Option<DateTime<Utc>>,
+    pub is_premium_only: bool,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HistoryEntry {
     pub id: String,
-    pub media_type: String,
+    pub media_type: MediaKind,
     pub content_id: Option<String>,
     pub series_id: Option<String>,
     pub movie_listing_id: Option<String>,
@@ -18,11 +53,112 @@ pub struct HistoryEntry {
     pub watched_at: Option<String>,
     pub playhead: Option<u32>,
     pub duration_ms: Option<u64>,
+    /// The playhead in milliseconds, capped at the duration when known.
+    #[serde(default)]
+    pub watched_ms: u64,
+    #[serde(default)]
+    pub watch_status: WatchStatus,
+    /// Finished plays of the same content before this one, as remembered
+    /// by the history store. Crunchyroll itself only keeps the latest play.
+    #[serde(default)]
+    pub rewatch_count: u32,
+    /// RFC 3339 time of the earliest known play of the content.
+    #[serde(default)]
+    pub first_watched_at: Option<String>,
+    /// Season, language and release details. Only set for episodes.
+    #[serde(default)]
+    pub episode_metadata: Option<EpisodeMetadata>,
     pub images: Vec<Image>,
-    pub genres: Vec<String>,
+    pub genres: Vec<Genre>,
+}
+
+impl HistoryEntry {
+    /// `watched_at` parsed back into a timestamp.
+    pub fn played_at(&self) -> Option<DateTime<Utc>> {
+        self.watched_at
+            .as_deref()
+            .and_then(|raw| DateTime::parse_from_rfc3339(raw).ok())
+            .map(|played| played.with_timezone(&Utc))
+    }
+
+    /// An unclassified episode of "Frieren" with nothing else known, for
+    /// tests to fill in with `..HistoryEntry::test(id, watched_at)`.
+    #[cfg(test)]
+    pub fn test(id: &str, watched_at: Option<&str>) -> Self {
+        Self {
+            id: id.to_string(),
+            media_type: MediaKind::Episode,
+            content_id: None,
+            series_id: None,
+            movie_listing_id: None,
+            title: "Frieren".to_string(),
+            episode_title: None,
+            watched_at: watched_at.map(str::to_string),
+            playhead: None,
+            duration_ms: None,
+            watched_ms: 0,
+            watch_status: WatchStatus::default(),
+            rewatch_count: 0,
+            first_watched_at: None,
+            episode_metadata: None,
+            images: vec![],
+            genres: vec![],
+        }
+    }
 }
 
 #[derive(Debug, Serialize)]
 pub struct HistoryResponse {
     pub data: Vec<HistoryEntry>,
+    #[serde(flatten)]
+    pub freshness: Freshness,
+}
+
+/// How old the history behind a response is.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct Freshness {
+    /// Past its TTL and being refreshed in the background; ask again
+    /// shortly for the refreshed history.
+    pub stale: bool,
+    /// Seconds since the history was fetched from Crunchyroll, 0 when it
+    /// was just fetched.
+    pub age_seconds: u64,
+}
+
+/// One line of a streamed history, or one server-sent event named after
+/// its `type`.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum HistoryEvent {
+    /// Entries as soon as their page is resolved, newest first. An entry
+    /// can be sent again once the fetch is complete; the later copy of an
+    /// id replaces the earlier one.
+    Entries { entries: Vec<HistoryEntry> },
+    Progress {
+        fetched: usize,
+        /// Oldest play fetched so far.
+        oldest_played_at: Option<DateTime<Utc>>,
+        /// How far back through the requested window the fetch has got,
+        /// from 0 to 1. `None` for the whole history, whose start is not
+        /// known up front.
+        fraction: Option<f64>,
+    },
+    /// Every entry has been sent; `items` is the size of the history.
+    Done {
+        items: usize,
+        #[serde(flatten)]
+        freshness: Freshness,
+    },
+    Error { error: String },
+}
+
+impl HistoryEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            HistoryEvent::Entries { .. } => "entries",
+            HistoryEvent::Progress { .. } => "progress",
+            HistoryEvent::Done { .. } => "done",
+            HistoryEvent::Error { .. } => "error",
+        }
+    }
 }
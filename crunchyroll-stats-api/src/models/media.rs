@@ -0,0 +1,164 @@
+use crunchyroll_rs::categories::Category;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MediaKind {
+    Episode,
+    Movie,
+}
+
+/// A Crunchyroll category. Serialized as the lowercase, hyphenated name
+/// Crunchyroll uses (`action`, `sci-fi`, `slice-of-life`, ...). Spelling
+/// variants map to the same genre so a renamed upstream category does not
+/// show up twice; anything unknown is kept as `Other`, normalized the same
+/// way.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Genre {
+    Action,
+    Adventure,
+    Comedy,
+    Drama,
+    Fantasy,
+    Harem,
+    Historical,
+    Idols,
+    Isekai,
+    Mecha,
+    Music,
+    Mystery,
+    PostApocalyptic,
+    Romance,
+    SciFi,
+    Seinen,
+    Shojo,
+    Shonen,
+    SliceOfLife,
+    Sports,
+    Supernatural,
+    Thriller,
+    Other(String),
+}
+
+impl Genre {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Genre::Action => "action",
+            Genre::Adventure => "adventure",
+            Genre::Comedy => "comedy",
+            Genre::Drama => "drama",
+            Genre::Fantasy => "fantasy",
+            Genre::Harem => "harem",
+            Genre::Historical => "historical",
+            Genre::Idols => "idols",
+            Genre::Isekai => "isekai",
+            Genre::Mecha => "mecha",
+            Genre::Music => "music",
+            Genre::Mystery => "mystery",
+            Genre::PostApocalyptic => "post-apocalyptic",
+            Genre::Romance => "romance",
+            Genre::SciFi => "sci-fi",
+            Genre::Seinen => "seinen",
+            Genre::Shojo => "shojo",
+            Genre::Shonen => "shonen",
+            Genre::SliceOfLife => "slice-of-life",
+            Genre::Sports => "sports",
+            Genre::Supernatural => "supernatural",
+            Genre::Thriller => "thriller",
+            Genre::Other(name) => name,
+        }
+    }
+}
+
+impl From<&str> for Genre {
+    fn from(name: &str) -> Self {
+        let normalized = name
+            .to_lowercase()
+            .split(|c: char| c.is_whitespace() || c == '-' || c == '_')
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>()
+            .join("-");
+        match normalized.as_str() {
+            "action" => Genre::Action,
+            "adventure" => Genre::Adventure,
+            "comedy" => Genre::Comedy,
+            "drama" => Genre::Drama,
+            "fantasy" => Genre::Fantasy,
+            "harem" => Genre::Harem,
+            "historical" | "history" => Genre::Historical,
+            "idols" | "idol" => Genre::Idols,
+            "isekai" => Genre::Isekai,
+            "mecha" => Genre::Mecha,
+            "music" => Genre::Music,
+            "mystery" => Genre::Mystery,
+            "post-apocalyptic" | "postapocalyptic" => Genre::PostApocalyptic,
+            "romance" => Genre::Romance,
+            "sci-fi" | "scifi" | "science-fiction" => Genre::SciFi,
+            "seinen" => Genre::Seinen,
+            "shojo" | "shoujo" => Genre::Shojo,
+            "shonen" | "shounen" => Genre::Shonen,
+            "slice-of-life" => Genre::SliceOfLife,
+            "sports" | "sport" => Genre::Sports,
+            "supernatural" => Genre::Supernatural,
+            "thriller" => Genre::Thriller,
+            _ => Genre::Other(normalized),
+        }
+    }
+}
+
+impl From<&Category> for Genre {
+    fn from(category: &Category) -> Self {
+        Genre::from(category.to_string().as_str())
+    }
+}
+
+impl fmt::Display for Genre {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Serialize for Genre {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Genre {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Genre::from(String::deserialize(deserializer)?.as_str()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spelling_variants_map_to_one_genre() {
+        for name in ["Sci-Fi", "sci fi", "SciFi", "science_fiction"] {
+            assert_eq!(Genre::from(name), Genre::SciFi, "{}", name);
+        }
+        assert_eq!(Genre::from("Slice of Life"), Genre::SliceOfLife);
+        assert_eq!(Genre::from("shoujo"), Genre::Shojo);
+        assert_eq!(Genre::from(&Category::SliceOfLife), Genre::SliceOfLife);
+    }
+
+    #[test]
+    fn unknown_genres_are_kept_normalized() {
+        assert_eq!(Genre::from(" Magical  Girl "), Genre::Other("magical-girl".to_string()));
+        assert_eq!(Genre::from(&Category::Custom("Magical Girl".to_string())).as_str(), "magical-girl");
+    }
+
+    #[test]
+    fn genres_serialize_as_names() {
+        let genres = vec![Genre::SliceOfLife, Genre::Other("magical-girl".to_string())];
+
+        let json = serde_json::to_string(&genres).unwrap();
+
+        assert_eq!(json, r#"["slice-of-life","magical-girl"]"#);
+        assert_eq!(serde_json::from_str::<Vec<Genre>>(&json).unwrap(), genres);
+        assert_eq!(serde_json::to_string(&MediaKind::Movie).unwrap(), r#""movie""#);
+    }
+}
@@ -1,7 +1,19 @@
+pub mod cache;
 pub mod history;
+pub mod media;
+pub mod stats;
 
-pub use history::{HistoryEntry, HistoryResponse, Image};
+pub use cache::{
+    CacheInvalidation, CacheReport, CachedHistoryReport, HistoryCacheReport, MetadataCacheReport,
+};
+pub use history::{EpisodeMetadata, Freshness, HistoryEntry, HistoryEvent, HistoryResponse, Image, WatchStatus};
+pub use media::{Genre, MediaKind};
+pub use stats::{
+    CompletionStatus, PeakDay, RewatchStats, SeasonCompletion, SeriesCompletion, StatsResponse, StatsSummary,
+    Streak, StreakStats, ViewingSession,
+};
 
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use validator::Validate;
 use zeroize::{Zeroize, ZeroizeOnDrop};
@@ -12,13 +24,67 @@ pub struct LoginRequest {
     pub email: String,
     #[validate(length(min = 1, max = 128))]
     pub password: String,
-    #[serde(default)]
-    pub force_refresh: bool,
 }
 
 #[derive(Debug, Serialize)]
 pub struct AuthResponse {
     pub success: bool,
+    /// Opaque session token for the `Authorization: Bearer` header.
+    pub token: String,
+    /// Public id of the session, for `DELETE /api/sessions/{id}`.
+    pub session_id: String,
+}
+
+/// One of the caller's live sessions, for `GET /api/sessions`.
+#[derive(Debug, Serialize)]
+pub struct SessionSummary {
+    /// For `DELETE /api/sessions/{id}`.
+    pub id: String,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: DateTime<Utc>,
+    /// Whether this is the session making the request.
+    pub current: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SessionsResponse {
+    /// Oldest first.
+    pub sessions: Vec<SessionSummary>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SuccessResponse {
+    pub success: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HistoryQuery {
+    #[serde(default)]
+    pub force_refresh: bool,
+    /// RFC 3339 lower bound. Defaults to the last 365 days.
+    pub since: Option<DateTime<Utc>>,
+    /// RFC 3339 upper bound. Defaults to now.
+    pub until: Option<DateTime<Utc>>,
+    /// Fetch the whole history. Cannot be combined with `since`.
+    #[serde(default)]
+    pub all: bool,
+    /// Share of an entry's duration, above 0 and up to 1, a play has to
+    /// reach to count as completed. Defaults to 0.9.
+    pub completion_threshold: Option<f64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TimezoneQuery {
+    /// IANA timezone to bucket plays into calendar days. Defaults to UTC.
+    pub tz: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BingeQuery {
+    /// Longest break between episodes that still counts as one session.
+    pub gap_minutes: Option<u32>,
+    /// Fewest episodes a session needs to count as a binge.
+    pub min_episodes: Option<usize>,
 }
 
 #[derive(Debug, Serialize)]
@@ -42,7 +108,6 @@ mod tests {
         let req = LoginRequest {
             email: "user@example.com".to_string(),
             password: "password123".to_string(),
-            force_refresh: false,
         };
         assert!(req.validate().is_ok());
     }
@@ -52,7 +117,6 @@ mod tests {
         let req = LoginRequest {
             email: "not-an-email".to_string(),
             password: "password123".to_string(),
-            force_refresh: false,
         };
         assert!(req.validate().is_err());
     }
@@ -62,7 +126,6 @@ mod tests {
         let req = LoginRequest {
             email: "user@example.com".to_string(),
             password: "".to_string(),
-            force_refresh: false,
         };
         assert!(req.validate().is_err());
     }
@@ -72,7 +135,6 @@ mod tests {
         let req = LoginRequest {
             email: "user@example.com".to_string(),
             password: "a".repeat(129),
-            force_refresh: false,
         };
         assert!(req.validate().is_err());
     }
@@ -84,7 +146,6 @@ mod tests {
         let req = LoginRequest {
             email: format!("{}@x.com", local),
             password: "password123".to_string(),
-            force_refresh: false,
         };
         assert!(req.validate().is_err());
     }
@@ -0,0 +1,117 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::Serialize;
+
+use super::Freshness;
+
+#[derive(Debug, Serialize)]
+pub struct StatsResponse<T> {
+    pub data: T,
+    #[serde(flatten)]
+    pub freshness: Freshness,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StatsSummary {
+    /// Unique show and movie titles, compared case-insensitively.
+    pub titles: usize,
+    pub series: usize,
+    pub movies: usize,
+    pub episodes: usize,
+    /// Entries by `watch_status`.
+    pub completed: usize,
+    pub partial: usize,
+    pub sampled: usize,
+    /// Sum of every entry's `watched_ms`.
+    pub watched_ms: u64,
+    pub watched_hours: f64,
+    /// Mean of playhead / duration over entries with both, from 0 to 1.
+    pub average_completion: f64,
+}
+
+/// A run of consecutive calendar days with at least one play.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Streak {
+    pub days: u32,
+    pub start: Option<NaiveDate>,
+    pub end: Option<NaiveDate>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PeakDay {
+    pub date: NaiveDate,
+    pub watched_ms: u64,
+    pub hours: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StreakStats {
+    /// IANA name of the timezone days were bucketed in.
+    pub timezone: String,
+    pub longest_streak: Streak,
+    /// The streak that includes today or, if nothing was played yet
+    /// today, yesterday. Zero days otherwise.
+    pub current_streak: Streak,
+    /// The day with the most watch time.
+    pub peak_day: Option<PeakDay>,
+}
+
+/// Back-to-back episodes of one series, see `stats::viewing_sessions`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ViewingSession {
+    pub series_id: String,
+    pub title: String,
+    pub episodes: usize,
+    pub watched_ms: u64,
+    pub watched_hours: f64,
+    /// When the first episode was played.
+    pub start: DateTime<Utc>,
+    /// When the last episode was played plus how long it was watched.
+    pub end: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CompletionStatus {
+    /// Every available episode was watched.
+    Finished,
+    /// Episodes are left and the series was played recently.
+    InProgress,
+    /// Episodes are left and the series has not been played in a while.
+    Dropped,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SeasonCompletion {
+    pub season_id: String,
+    pub season_number: u32,
+    pub title: String,
+    pub watched: usize,
+    pub available: usize,
+    /// `watched / available`, from 0 to 1.
+    pub completion: f64,
+}
+
+/// Episodes watched against episodes available, see `stats::completion`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SeriesCompletion {
+    pub series_id: String,
+    pub title: String,
+    pub watched: usize,
+    pub available: usize,
+    pub completion: f64,
+    pub status: CompletionStatus,
+    pub last_watched_at: Option<DateTime<Utc>>,
+    pub seasons: Vec<SeasonCompletion>,
+}
+
+/// Watch time split by whether the play was a rewatch, see
+/// `stats::rewatches`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RewatchStats {
+    pub new_entries: usize,
+    pub new_ms: u64,
+    pub new_hours: f64,
+    pub rewatched_entries: usize,
+    pub rewatched_ms: u64,
+    pub rewatched_hours: f64,
+}
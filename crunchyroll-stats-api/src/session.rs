@@ -0,0 +1,337 @@
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{watch, RwLock};
+
+use crate::crypto::HistoryKey;
+
+const IDLE_TTL: Duration = Duration::from_secs(24 * 60 * 60); // 24 hours
+const MAX_AGE: Duration = Duration::from_secs(30 * 24 * 60 * 60); // 30 days
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(5 * 60); // 5 minutes
+
+/// A logged-in upstream session handed out to a client as an opaque token.
+pub struct UserSession<T> {
+    /// Public identifier, safe to show and used for revocation. Unlike the
+    /// token it grants no access.
+    pub id: String,
+    /// `AppCache::cache_key` of the account's email.
+    pub user_key: String,
+    /// Decrypts the user's stored history; dropped with the session.
+    pub history_key: HistoryKey,
+    pub upstream: T,
+    /// Set once the session is revoked or expires.
+    ended: watch::Sender<bool>,
+}
+
+impl<T> UserSession<T> {
+    pub fn is_ended(&self) -> bool {
+        *self.ended.borrow()
+    }
+
+    /// Resolves once the session is revoked or expires, so work done on its
+    /// behalf can stop with it.
+    pub async fn ended(&self) {
+        let mut ended = self.ended.subscribe();
+        // The sender lives as long as `self`.
+        let _ = ended.wait_for(|ended| *ended).await;
+    }
+
+    fn end(&self) {
+        self.ended.send_replace(true);
+    }
+}
+
+/// A live session as listed to its owner, without its token.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionInfo {
+    pub id: String,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: DateTime<Utc>,
+}
+
+struct SessionEntry<T> {
+    session: Arc<UserSession<T>>,
+    created_at: Instant,
+    last_used: Instant,
+}
+
+impl<T> SessionEntry<T> {
+    fn is_expired(&self, idle_ttl: Duration, max_age: Duration) -> bool {
+        self.last_used.elapsed() > idle_ttl || self.created_at.elapsed() > max_age
+    }
+}
+
+/// Maps opaque session tokens to live upstream sessions, so clients only send
+/// credentials once, to `/api/auth`.
+/// - Tokens are kept as SHA256 hashes; the raw token only ever exists on the
+///   client
+/// - Sessions expire after `IDLE_TTL` without use or `MAX_AGE` in total
+/// - Revoking a session drops the upstream Crunchyroll session with it
+pub struct SessionStore<T> {
+    sessions: RwLock<HashMap<String, SessionEntry<T>>>,
+    idle_ttl: Duration,
+    max_age: Duration,
+}
+
+impl<T: Send + Sync + 'static> SessionStore<T> {
+    pub fn new() -> Arc<Self> {
+        Self::with_ttls(IDLE_TTL, MAX_AGE)
+    }
+
+    fn with_ttls(idle_ttl: Duration, max_age: Duration) -> Arc<Self> {
+        let store = Arc::new(Self {
+            sessions: RwLock::new(HashMap::new()),
+            idle_ttl,
+            max_age,
+        });
+
+        // Periodic cleanup of expired sessions
+        let store_clone = store.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(CLEANUP_INTERVAL).await;
+                let mut sessions = store_clone.sessions.write().await;
+                sessions.retain(|_, e| {
+                    let expired = e.is_expired(store_clone.idle_ttl, store_clone.max_age);
+                    if expired {
+                        e.session.end();
+                    }
+                    !expired
+                });
+            }
+        });
+
+        store
+    }
+}
+
+impl<T> SessionStore<T> {
+    fn token_key(token: &str) -> String {
+        use sha2::{Digest, Sha256};
+        let mut hasher = Sha256::new();
+        hasher.update(token.as_bytes());
+        hex::encode(hasher.finalize())
+    }
+
+    /// Stores the session and returns it along with the token that refers to it.
+    pub async fn create(
+        &self,
+        user_key: String,
+        history_key: HistoryKey,
+        upstream: T,
+    ) -> (String, Arc<UserSession<T>>) {
+        let token = hex::encode(rand::random::<[u8; 32]>());
+        let session = Arc::new(UserSession {
+            id: hex::encode(rand::random::<[u8; 16]>()),
+            user_key,
+            history_key,
+            upstream,
+            ended: watch::Sender::new(false),
+        });
+        let now = Instant::now();
+        let mut sessions = self.sessions.write().await;
+        sessions.insert(
+            Self::token_key(&token),
+            SessionEntry {
+                session: session.clone(),
+                created_at: now,
+                last_used: now,
+            },
+        );
+        (token, session)
+    }
+
+    /// Looks up a live session and refreshes its idle timer. Expired
+    /// sessions are removed on the spot.
+    pub async fn get(&self, token: &str) -> Option<Arc<UserSession<T>>> {
+        let key = Self::token_key(token);
+        let mut sessions = self.sessions.write().await;
+        let entry = sessions.get_mut(&key)?;
+        if entry.is_expired(self.idle_ttl, self.max_age) {
+            entry.session.end();
+            sessions.remove(&key);
+            return None;
+        }
+        entry.last_used = Instant::now();
+        Some(entry.session.clone())
+    }
+
+    /// Revokes the session the token refers to. Returns whether one existed.
+    pub async fn revoke(&self, token: &str) -> bool {
+        let mut sessions = self.sessions.write().await;
+        let Some(entry) = sessions.remove(&Self::token_key(token)) else {
+            return false;
+        };
+        entry.session.end();
+        true
+    }
+
+    /// Revokes a session by its public id, but only if it belongs to
+    /// `user_key`. Returns whether one was removed.
+    pub async fn revoke_by_id(&self, user_key: &str, id: &str) -> bool {
+        let mut sessions = self.sessions.write().await;
+        let mut revoked = false;
+        sessions.retain(|_, e| {
+            let matches = e.session.id == id && e.session.user_key == user_key;
+            if matches {
+                e.session.end();
+                revoked = true;
+            }
+            !matches
+        });
+        revoked
+    }
+
+    /// Live sessions of `user_key`, oldest first.
+    pub async fn list_for(&self, user_key: &str) -> Vec<SessionInfo> {
+        let (now, wall_now) = (Instant::now(), Utc::now());
+        let at = |instant: Instant| {
+            wall_now - chrono::Duration::from_std(now - instant).unwrap_or(chrono::Duration::zero())
+        };
+        let sessions = self.sessions.read().await;
+        let mut listed: Vec<SessionInfo> = sessions
+            .values()
+            .filter(|e| {
+                e.session.user_key == user_key && !e.is_expired(self.idle_ttl, self.max_age)
+            })
+            .map(|e| SessionInfo {
+                id: e.session.id.clone(),
+                created_at: at(e.created_at),
+                last_used_at: at(e.last_used),
+            })
+            .collect();
+        listed.sort_by_key(|session| session.created_at);
+        listed
+    }
+
+    /// Number of live sessions held for `user_key`.
+    #[cfg(test)]
+    pub async fn count_for(&self, user_key: &str) -> usize {
+        let sessions = self.sessions.read().await;
+        sessions
+            .values()
+            .filter(|e| {
+                e.session.user_key == user_key && !e.is_expired(self.idle_ttl, self.max_age)
+            })
+            .count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn create_returns_64_char_hex_token() {
+        let store = SessionStore::new();
+        let (token, _) = store.create("user-key".to_string(), HistoryKey::test(), ()).await;
+        assert_eq!(token.len(), 64);
+        assert!(token.chars().all(|c| c.is_ascii_hexdigit()));
+    }
+
+    #[tokio::test]
+    async fn tokens_and_ids_are_unique() {
+        let store = SessionStore::new();
+        let (token1, session1) = store.create("user-key".to_string(), HistoryKey::test(), ()).await;
+        let (token2, session2) = store.create("user-key".to_string(), HistoryKey::test(), ()).await;
+        assert_ne!(token1, token2);
+        assert_ne!(session1.id, session2.id);
+    }
+
+    #[tokio::test]
+    async fn get_returns_created_session() {
+        let store = SessionStore::new();
+        let (token, _) = store.create("user-key".to_string(), HistoryKey::test(), 42).await;
+
+        let session = store.get(&token).await.unwrap();
+        assert_eq!(session.user_key, "user-key");
+        assert_eq!(session.upstream, 42);
+    }
+
+    #[tokio::test]
+    async fn get_unknown_token_returns_none() {
+        let store: Arc<SessionStore<()>> = SessionStore::new();
+        assert!(store.get("nonexistent").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn raw_token_is_not_stored() {
+        let store = SessionStore::new();
+        let (token, _) = store.create("user-key".to_string(), HistoryKey::test(), ()).await;
+        let sessions = store.sessions.read().await;
+        assert!(!sessions.contains_key(&token));
+    }
+
+    #[tokio::test]
+    async fn idle_session_expires() {
+        let store = SessionStore::with_ttls(Duration::ZERO, MAX_AGE);
+        let (token, session) = store.create("user-key".to_string(), HistoryKey::test(), ()).await;
+        tokio::time::sleep(Duration::from_millis(5)).await;
+
+        assert!(store.get(&token).await.is_none());
+        assert_eq!(store.count_for("user-key").await, 0);
+        assert!(session.is_ended());
+    }
+
+    #[tokio::test]
+    async fn session_expires_after_max_age_despite_use() {
+        let store = SessionStore::with_ttls(IDLE_TTL, Duration::from_millis(20));
+        let (token, _) = store.create("user-key".to_string(), HistoryKey::test(), ()).await;
+        assert!(store.get(&token).await.is_some());
+        tokio::time::sleep(Duration::from_millis(30)).await;
+
+        assert!(store.get(&token).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn revoke_removes_session() {
+        let store = SessionStore::new();
+        let (token, session) = store.create("user-key".to_string(), HistoryKey::test(), ()).await;
+        assert!(!session.is_ended());
+
+        assert!(store.revoke(&token).await);
+        assert!(store.get(&token).await.is_none());
+        assert!(!store.revoke(&token).await);
+        assert!(session.is_ended());
+        tokio::time::timeout(Duration::from_secs(1), session.ended()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn revoke_by_id_removes_only_matching_session() {
+        let store = SessionStore::new();
+        let (token1, session1) = store.create("user-key".to_string(), HistoryKey::test(), ()).await;
+        let (token2, session2) = store.create("user-key".to_string(), HistoryKey::test(), ()).await;
+
+        assert!(store.revoke_by_id("user-key", &session1.id).await);
+        assert!(store.get(&token1).await.is_none());
+        assert!(store.get(&token2).await.is_some());
+        assert!(session1.is_ended() && !session2.is_ended());
+        assert_eq!(store.count_for("user-key").await, 1);
+    }
+
+    #[tokio::test]
+    async fn revoke_by_id_ignores_other_users_sessions() {
+        let store = SessionStore::new();
+        let (token, session) = store.create("alice".to_string(), HistoryKey::test(), ()).await;
+
+        assert!(!store.revoke_by_id("bob", &session.id).await);
+        assert!(store.get(&token).await.is_some());
+    }
+
+    #[tokio::test]
+    async fn list_for_returns_only_the_users_live_sessions() {
+        let store = SessionStore::new();
+        let (_, first) = store.create("alice".to_string(), HistoryKey::test(), ()).await;
+        let (_, second) = store.create("alice".to_string(), HistoryKey::test(), ()).await;
+        store.create("bob".to_string(), HistoryKey::test(), ()).await;
+
+        let listed = store.list_for("alice").await;
+
+        let ids: Vec<_> = listed.iter().map(|session| session.id.as_str()).collect();
+        assert_eq!(ids.len(), 2);
+        assert!(ids.contains(&first.id.as_str()) && ids.contains(&second.id.as_str()));
+        assert!(listed.iter().all(|session| session.last_used_at >= session.created_at));
+        assert!(store.list_for("carol").await.is_empty());
+    }
+}
@@ -0,0 +1,260 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use crunchyroll_rs::categories::Category;
+use crunchyroll_rs::list::WatchHistoryEntry;
+use crunchyroll_rs::{Episode, MediaCollection, Movie, MovieListing, Season, Series};
+use futures_util::stream::{self, BoxStream};
+use futures_util::StreamExt;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use super::WatchDataSource;
+
+/// In-memory stand-in for Crunchyroll. Lookups for ids that were never
+/// registered fail, which is how tests exercise the fallback paths.
+#[derive(Default)]
+pub struct FixtureSource {
+    accounts: HashMap<String, String>,
+    history: Vec<WatchHistoryEntry>,
+    media: HashMap<String, MediaCollection>,
+    series: HashMap<String, Series>,
+    movie_listings: HashMap<String, MovieListing>,
+    seasons: HashMap<String, Vec<Season>>,
+    episodes: HashMap<String, Vec<Episode>>,
+    series_lookups: AtomicUsize,
+    movie_listing_lookups: AtomicUsize,
+    episode_lookups: AtomicUsize,
+    metadata_concurrency: Option<usize>,
+    latencies: HashMap<String, Duration>,
+    in_flight: AtomicUsize,
+    max_in_flight: AtomicUsize,
+}
+
+impl FixtureSource {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_account(mut self, email: &str, password: &str) -> Self {
+        self.accounts.insert(email.to_string(), password.to_string());
+        self
+    }
+
+    /// Appends a history entry. Entries are served in insertion order, so
+    /// add them newest first like the real API.
+    pub fn with_entry(mut self, entry: WatchHistoryEntry) -> Self {
+        self.history.push(entry);
+        self
+    }
+
+    /// Registers media that `media_collection_from_id` can resolve.
+    pub fn with_media(mut self, id: &str, media: MediaCollection) -> Self {
+        self.media.insert(id.to_string(), media);
+        self
+    }
+
+    pub fn with_series(mut self, series: Series) -> Self {
+        self.series.insert(series.id.clone(), series);
+        self
+    }
+
+    pub fn with_movie_listing(mut self, listing: MovieListing) -> Self {
+        self.movie_listings.insert(listing.id.clone(), listing);
+        self
+    }
+
+    /// Adds `season` to its series' season list, with `episodes` as its
+    /// episode list.
+    pub fn with_season(mut self, season: Season, episodes: Vec<Episode>) -> Self {
+        self.episodes.insert(season.id.clone(), episodes);
+        self.seasons.entry(season.series_id.clone()).or_default().push(season);
+        self
+    }
+
+    pub fn with_metadata_concurrency(mut self, concurrency: usize) -> Self {
+        self.metadata_concurrency = Some(concurrency);
+        self
+    }
+
+    /// Delays panel, series and movie listing lookups of `id`.
+    pub fn with_latency(mut self, id: &str, latency: Duration) -> Self {
+        self.latencies.insert(id.to_string(), latency);
+        self
+    }
+
+    /// The most panel, series and movie listing lookups that were in
+    /// flight at the same time.
+    pub fn max_in_flight(&self) -> usize {
+        self.max_in_flight.load(Ordering::SeqCst)
+    }
+
+    async fn lookup(&self, id: &str) {
+        let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+        self.max_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+        match self.latencies.get(id) {
+            Some(latency) => tokio::time::sleep(*latency).await,
+            None => tokio::task::yield_now().await,
+        }
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    pub fn series_lookups(&self) -> usize {
+        self.series_lookups.load(Ordering::SeqCst)
+    }
+
+    pub fn movie_listing_lookups(&self) -> usize {
+        self.movie_listing_lookups.load(Ordering::SeqCst)
+    }
+
+    pub fn episode_lookups(&self) -> usize {
+        self.episode_lookups.load(Ordering::SeqCst)
+    }
+}
+
+impl WatchDataSource for FixtureSource {
+    type Session = String;
+
+    async fn login(&self, email: &str, password: &str) -> Result<String> {
+        match self.accounts.get(email) {
+            Some(expected) if expected == password => Ok(email.to_string()),
+            _ => Err(anyhow!("invalid credentials")),
+        }
+    }
+
+    fn watch_history<'a>(
+        &'a self,
+        _session: &'a String,
+        _page_size: Option<u32>,
+    ) -> BoxStream<'a, Result<WatchHistoryEntry>> {
+        stream::iter(self.history.iter().cloned().map(Ok)).boxed()
+    }
+
+    async fn media_collection_from_id(&self, _session: &String, id: &str) -> Result<MediaCollection> {
+        self.lookup(id).await;
+        self.media
+            .get(id)
+            .cloned()
+            .ok_or_else(|| anyhow!("no media with id {}", id))
+    }
+
+    async fn series(&self, _session: &String, series_id: &str) -> Result<Series> {
+        self.series_lookups.fetch_add(1, Ordering::SeqCst);
+        self.lookup(series_id).await;
+        self.series
+            .get(series_id)
+            .cloned()
+            .ok_or_else(|| anyhow!("no series with id {}", series_id))
+    }
+
+    async fn movie_listing(&self, _session: &String, movie_listing_id: &str) -> Result<MovieListing> {
+        self.movie_listing_lookups.fetch_add(1, Ordering::SeqCst);
+        self.lookup(movie_listing_id).await;
+        self.movie_listings
+            .get(movie_listing_id)
+            .cloned()
+            .ok_or_else(|| anyhow!("no movie listing with id {}", movie_listing_id))
+    }
+
+    async fn seasons(&self, _session: &String, series: &Series) -> Result<Vec<Season>> {
+        self.seasons
+            .get(&series.id)
+            .cloned()
+            .ok_or_else(|| anyhow!("no seasons for series {}", series.id))
+    }
+
+    async fn episodes(&self, _session: &String, season: &Season) -> Result<Vec<Episode>> {
+        self.episode_lookups.fetch_add(1, Ordering::SeqCst);
+        self.episodes
+            .get(&season.id)
+            .cloned()
+            .ok_or_else(|| anyhow!("no episodes for season {}", season.id))
+    }
+
+    fn metadata_concurrency(&self) -> usize {
+        self.metadata_concurrency.unwrap_or(super::DEFAULT_METADATA_CONCURRENCY)
+    }
+}
+
+pub fn episode(id: &str, series_id: &str, series_title: &str, title: &str) -> Episode {
+    let mut episode = Episode::default();
+    episode.id = id.to_string();
+    episode.series_id = series_id.to_string();
+    episode.series_title = series_title.to_string();
+    episode.title = title.to_string();
+    episode.duration = chrono::Duration::minutes(24);
+    episode
+}
+
+pub fn season(id: &str, series_id: &str, season_number: u32) -> Season {
+    let mut season = Season::default();
+    season.id = id.to_string();
+    season.series_id = series_id.to_string();
+    season.season_number = season_number;
+    season.title = format!("Season {}", season_number);
+    season
+}
+
+pub fn movie(id: &str, movie_listing_id: &str, title: &str) -> Movie {
+    let mut movie = Movie::default();
+    movie.id = id.to_string();
+    movie.movie_listing_id = movie_listing_id.to_string();
+    movie.title = title.to_string();
+    movie.duration = chrono::Duration::minutes(110);
+    movie
+}
+
+pub fn series(id: &str, genres: &[&str]) -> Series {
+    let mut series = Series::default();
+    series.id = id.to_string();
+    series.categories = Some(categories(genres));
+    series
+}
+
+pub fn movie_listing(id: &str, genres: &[&str]) -> MovieListing {
+    let mut listing = MovieListing::default();
+    listing.id = id.to_string();
+    listing.categories = Some(categories(genres));
+    listing
+}
+
+pub fn categories(genres: &[&str]) -> Vec<Category> {
+    genres.iter().map(|genre| Category::from(genre.to_string())).collect()
+}
+
+/// A history entry carrying its panel, like almost every real entry.
+pub fn played(panel: MediaCollection, date_played: DateTime<Utc>, playhead: u32) -> WatchHistoryEntry {
+    let (id, parent_id, parent_type) = match &panel {
+        MediaCollection::Episode(episode) => (episode.id.clone(), episode.series_id.clone(), "series"),
+        MediaCollection::Movie(movie) => (movie.id.clone(), movie.movie_listing_id.clone(), "movie_listing"),
+        _ => panic!("fixture history entries must be episodes or movies"),
+    };
+
+    WatchHistoryEntry {
+        id,
+        parent_id,
+        parent_type: parent_type.to_string(),
+        date_played,
+        playhead,
+        fully_watched: false,
+        panel: Some(panel),
+    }
+}
+
+/// A history entry whose panel is missing and must be resolved by id.
+pub fn played_without_panel(
+    id: &str,
+    parent_id: &str,
+    date_played: DateTime<Utc>,
+    playhead: u32,
+) -> WatchHistoryEntry {
+    WatchHistoryEntry {
+        id: id.to_string(),
+        parent_id: parent_id.to_string(),
+        parent_type: "series".to_string(),
+        date_played,
+        playhead,
+        fully_watched: false,
+        panel: None,
+    }
+}
@@ -0,0 +1,265 @@
+use actix_web::dev::ServerHandle;
+use actix_web::http::StatusCode;
+use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::Value;
+use std::net::{SocketAddr, TcpListener};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+pub const EMAIL: &str = "mock-user@example.com";
+pub const PASSWORD: &str = "mock-password";
+
+const HOST: &str = "www.crunchyroll.com";
+
+/// Serves recorded Crunchyroll responses from `tests/fixtures/crunchyroll`
+/// over TLS on localhost. Clients from `http_client` resolve
+/// `www.crunchyroll.com` to this server and trust its self-signed
+/// certificate, so the real crunchyroll-rs client can log in and paginate
+/// against it unchanged.
+///
+/// Fixture layout:
+/// - `token.json` for `POST /auth/v1/token`
+/// - `watch_history.json` for the paginated watch history
+/// - `{episodes,movies,series,seasons,movie_listings}/{id}.json` for
+///   `/content/v2/cms/{kind}/{id}`; missing files answer 404
+pub struct MockCrunchyroll {
+    addr: SocketAddr,
+    certificate_der: Vec<u8>,
+    requests: Arc<Mutex<Vec<String>>>,
+    handle: ServerHandle,
+}
+
+struct MockState {
+    fixtures: PathBuf,
+    token: Value,
+    history: Vec<Value>,
+    requests: Arc<Mutex<Vec<String>>>,
+}
+
+#[derive(Deserialize)]
+struct TokenForm {
+    grant_type: String,
+    #[serde(default)]
+    username: String,
+    #[serde(default)]
+    password: String,
+}
+
+#[derive(Deserialize)]
+struct PageQuery {
+    page: usize,
+    page_size: usize,
+}
+
+impl MockCrunchyroll {
+    pub async fn start() -> Self {
+        let fixtures = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/crunchyroll");
+        let token = read_json(&fixtures.join("token.json"));
+        let history = rebase_history(read_json(&fixtures.join("watch_history.json")));
+
+        let certified = rcgen::generate_simple_self_signed(vec![HOST.to_string()])
+            .expect("failed to generate mock certificate");
+        let certificate_der = certified.cert.der().to_vec();
+        let tls = rustls::ServerConfig::builder_with_provider(Arc::new(
+            rustls::crypto::ring::default_provider(),
+        ))
+        .with_safe_default_protocol_versions()
+        .expect("invalid TLS protocol versions")
+        .with_no_client_auth()
+        .with_single_cert(
+            vec![certified.cert.der().clone()],
+            rustls::pki_types::PrivateKeyDer::Pkcs8(certified.signing_key.serialize_der().into()),
+        )
+        .expect("invalid mock certificate");
+
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let state = web::Data::new(MockState {
+            fixtures,
+            token,
+            history,
+            requests: requests.clone(),
+        });
+
+        let listener = TcpListener::bind("127.0.0.1:0").expect("failed to bind mock server");
+        let addr = listener.local_addr().expect("mock server has no address");
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(state.clone())
+                .route("/", web::get().to(index))
+                .route("/auth/v1/token", web::post().to(issue_token))
+                .route("/content/v2/{account_id}/watch-history", web::get().to(watch_history))
+                .route("/content/v2/cms/{kind}/{id}", web::get().to(media))
+                .route("/content/v2/cms/{kind}/{id}/{list}", web::get().to(media_list))
+                .default_service(web::to(not_found))
+        })
+        .workers(1)
+        .disable_signals()
+        .listen_rustls_0_23(listener, tls)
+        .expect("failed to start mock server")
+        .run();
+        let handle = server.handle();
+        tokio::spawn(server);
+
+        Self {
+            addr,
+            certificate_der,
+            requests,
+            handle,
+        }
+    }
+
+    /// A client configured like crunchyroll-rs' own, but pointed at this server.
+    pub fn http_client(&self) -> reqwest::Client {
+        let mut roots = rustls::RootCertStore::empty();
+        roots
+            .add(self.certificate_der.clone().into())
+            .expect("invalid mock certificate");
+        let tls = rustls::ClientConfig::builder_with_provider(Arc::new(
+            rustls::crypto::ring::default_provider(),
+        ))
+        .with_safe_default_protocol_versions()
+        .expect("invalid TLS protocol versions")
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+
+        crunchyroll_rs::crunchyroll::CrunchyrollBuilder::predefined_client_builder()
+            .tls_backend_preconfigured(tls)
+            .resolve(HOST, self.addr)
+            .build()
+            .expect("failed to build mock client")
+    }
+
+    /// Paths of every request received so far, in order.
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+impl Drop for MockCrunchyroll {
+    fn drop(&mut self) {
+        let handle = self.handle.clone();
+        tokio::spawn(async move { handle.stop(false).await });
+    }
+}
+
+fn read_json(path: &PathBuf) -> Value {
+    let raw = std::fs::read_to_string(path)
+        .unwrap_or_else(|error| panic!("failed to read {}: {}", path.display(), error));
+    serde_json::from_str(&raw)
+        .unwrap_or_else(|error| panic!("failed to parse {}: {}", path.display(), error))
+}
+
+/// Shifts every `date_played` so the newest play happened just now, which
+/// keeps the recording inside `fetch_history`'s cutoff however old it is.
+fn rebase_history(recorded: Value) -> Vec<Value> {
+    let mut entries = recorded["data"].as_array().cloned().unwrap_or_default();
+    let played = |entry: &Value| {
+        entry["date_played"]
+            .as_str()
+            .and_then(|raw| DateTime::parse_from_rfc3339(raw).ok())
+            .map(|date| date.with_timezone(&Utc))
+    };
+    let Some(newest) = entries.iter().filter_map(played).max() else {
+        return entries;
+    };
+    let offset = Utc::now() - newest;
+    for entry in &mut entries {
+        if let Some(date) = played(entry) {
+            entry["date_played"] = Value::String((date + offset).to_rfc3339());
+        }
+    }
+    entries
+}
+
+fn record(state: &MockState, req: &HttpRequest) {
+    state.requests.lock().unwrap().push(req.path().to_string());
+}
+
+fn error(status: StatusCode, code: &str) -> HttpResponse {
+    HttpResponse::build(status).json(serde_json::json!({ "code": code, "message": code }))
+}
+
+async fn index(req: HttpRequest, state: web::Data<MockState>) -> HttpResponse {
+    record(&state, &req);
+    HttpResponse::Ok().content_type("text/html").body("<!DOCTYPE html><html></html>")
+}
+
+async fn issue_token(
+    req: HttpRequest,
+    form: web::Form<TokenForm>,
+    state: web::Data<MockState>,
+) -> HttpResponse {
+    record(&state, &req);
+    let authorized = match form.grant_type.as_str() {
+        "password" => form.username == EMAIL && form.password == PASSWORD,
+        "refresh_token" => true,
+        _ => false,
+    };
+    if authorized {
+        HttpResponse::Ok().json(&state.token)
+    } else {
+        HttpResponse::Unauthorized().json(serde_json::json!({ "error": "invalid_grant" }))
+    }
+}
+
+async fn watch_history(
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<PageQuery>,
+    state: web::Data<MockState>,
+) -> HttpResponse {
+    record(&state, &req);
+    if state.token["account_id"].as_str() != Some(path.as_str()) {
+        return error(StatusCode::FORBIDDEN, "accounts.get_account.forbidden");
+    }
+
+    let start = query.page.saturating_sub(1) * query.page_size;
+    let page: Vec<Value> = state
+        .history
+        .iter()
+        .skip(start)
+        .take(query.page_size)
+        .cloned()
+        .collect();
+    HttpResponse::Ok().json(serde_json::json!({
+        "total": state.history.len(),
+        "data": page,
+        "meta": {},
+    }))
+}
+
+async fn media(
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+    state: web::Data<MockState>,
+) -> HttpResponse {
+    record(&state, &req);
+    let (kind, id) = path.into_inner();
+    let file = state.fixtures.join(&kind).join(format!("{}.json", id));
+    if kind.contains("..") || id.contains("..") || !file.is_file() {
+        return error(StatusCode::NOT_FOUND, "resource.not_found");
+    }
+    HttpResponse::Ok().json(read_json(&file))
+}
+
+/// Listings such as a series' seasons, served from `{kind}/{id}/{list}.json`.
+async fn media_list(
+    req: HttpRequest,
+    path: web::Path<(String, String, String)>,
+    state: web::Data<MockState>,
+) -> HttpResponse {
+    record(&state, &req);
+    let (kind, id, list) = path.into_inner();
+    let file = state.fixtures.join(&kind).join(&id).join(format!("{}.json", list));
+    if [&kind, &id, &list].iter().any(|part| part.contains("..")) || !file.is_file() {
+        return error(StatusCode::NOT_FOUND, "resource.not_found");
+    }
+    HttpResponse::Ok().json(read_json(&file))
+}
+
+async fn not_found(req: HttpRequest, state: web::Data<MockState>) -> HttpResponse {
+    record(&state, &req);
+    error(StatusCode::NOT_FOUND, "resource.not_found")
+}
@@ -0,0 +1,174 @@
+#[cfg(test)]
+pub mod fixture;
+#[cfg(test)]
+pub mod mock_server;
+
+use anyhow::{anyhow, Result};
+use crunchyroll_rs::list::WatchHistoryEntry;
+use crunchyroll_rs::{Episode, MediaCollection, MovieListing, Season, Series};
+use futures_util::stream::BoxStream;
+use futures_util::{StreamExt, TryStreamExt};
+use std::future::Future;
+
+use crate::auth::CrunchyrollClient;
+
+pub const DEFAULT_METADATA_CONCURRENCY: usize = 8;
+
+/// Everything `History::fetch_history` needs from Crunchyroll.
+/// Implemented by `CrunchyrollSource` for the real API and by
+/// `fixture::FixtureSource` for offline tests.
+pub trait WatchDataSource: Send + Sync + 'static {
+    /// An authenticated upstream session.
+    type Session: Send + Sync;
+
+    fn login(
+        &self,
+        email: &str,
+        password: &str,
+    ) -> impl Future<Output = Result<Self::Session>> + Send;
+
+    /// Watch history, newest first, one entry at a time across pages.
+    fn watch_history<'a>(
+        &'a self,
+        session: &'a Self::Session,
+        page_size: Option<u32>,
+    ) -> BoxStream<'a, Result<WatchHistoryEntry>>;
+
+    fn media_collection_from_id(
+        &self,
+        session: &Self::Session,
+        id: &str,
+    ) -> impl Future<Output = Result<MediaCollection>> + Send;
+
+    fn series(
+        &self,
+        session: &Self::Session,
+        series_id: &str,
+    ) -> impl Future<Output = Result<Series>> + Send;
+
+    fn movie_listing(
+        &self,
+        session: &Self::Session,
+        movie_listing_id: &str,
+    ) -> impl Future<Output = Result<MovieListing>> + Send;
+
+    /// Every season of `series`, including one per dub where Crunchyroll
+    /// lists them separately.
+    fn seasons(
+        &self,
+        session: &Self::Session,
+        series: &Series,
+    ) -> impl Future<Output = Result<Vec<Season>>> + Send;
+
+    fn episodes(
+        &self,
+        session: &Self::Session,
+        season: &Season,
+    ) -> impl Future<Output = Result<Vec<Episode>>> + Send;
+
+    /// How many panel, series and movie listing lookups a history fetch
+    /// may have in flight at once.
+    fn metadata_concurrency(&self) -> usize {
+        DEFAULT_METADATA_CONCURRENCY
+    }
+}
+
+/// The live Crunchyroll API via crunchyroll-rs.
+pub struct CrunchyrollSource {
+    http_client: Option<reqwest::Client>,
+    metadata_concurrency: usize,
+}
+
+impl Default for CrunchyrollSource {
+    fn default() -> Self {
+        Self {
+            http_client: None,
+            metadata_concurrency: DEFAULT_METADATA_CONCURRENCY,
+        }
+    }
+}
+
+impl CrunchyrollSource {
+    /// Reads the lookup concurrency from `METADATA_CONCURRENCY`.
+    pub fn from_env() -> Result<Self> {
+        let metadata_concurrency = match std::env::var("METADATA_CONCURRENCY") {
+            Ok(raw) => raw
+                .parse()
+                .ok()
+                .filter(|concurrency| *concurrency > 0)
+                .ok_or_else(|| {
+                    anyhow!("METADATA_CONCURRENCY must be a positive integer, got {:?}", raw)
+                })?,
+            Err(_) => DEFAULT_METADATA_CONCURRENCY,
+        };
+        Ok(Self {
+            metadata_concurrency,
+            ..Self::default()
+        })
+    }
+
+    /// Sends every upstream request through `http_client` instead of the
+    /// crunchyroll-rs default client.
+    #[cfg(test)]
+    pub fn with_http_client(http_client: reqwest::Client) -> Self {
+        Self {
+            http_client: Some(http_client),
+            ..Self::default()
+        }
+    }
+}
+
+impl WatchDataSource for CrunchyrollSource {
+    type Session = CrunchyrollClient;
+
+    async fn login(&self, email: &str, password: &str) -> Result<CrunchyrollClient> {
+        CrunchyrollClient::new(email, password, self.http_client.clone()).await
+    }
+
+    fn watch_history<'a>(
+        &'a self,
+        session: &'a CrunchyrollClient,
+        page_size: Option<u32>,
+    ) -> BoxStream<'a, Result<WatchHistoryEntry>> {
+        let mut pagination = session.client.watch_history();
+        if let Some(page_size) = page_size {
+            pagination.page_size(page_size);
+        }
+        pagination.map_err(Into::into).boxed()
+    }
+
+    async fn media_collection_from_id(
+        &self,
+        session: &CrunchyrollClient,
+        id: &str,
+    ) -> Result<MediaCollection> {
+        Ok(session.client.media_collection_from_id(id).await?)
+    }
+
+    async fn series(&self, session: &CrunchyrollClient, series_id: &str) -> Result<Series> {
+        Ok(session.client.media_from_id::<Series>(series_id).await?)
+    }
+
+    async fn movie_listing(
+        &self,
+        session: &CrunchyrollClient,
+        movie_listing_id: &str,
+    ) -> Result<MovieListing> {
+        Ok(session
+            .client
+            .media_from_id::<MovieListing>(movie_listing_id)
+            .await?)
+    }
+
+    async fn seasons(&self, _session: &CrunchyrollClient, series: &Series) -> Result<Vec<Season>> {
+        Ok(series.seasons().await?)
+    }
+
+    async fn episodes(&self, _session: &CrunchyrollClient, season: &Season) -> Result<Vec<Episode>> {
+        Ok(season.episodes().await?)
+    }
+
+    fn metadata_concurrency(&self) -> usize {
+        self.metadata_concurrency
+    }
+}
@@ -0,0 +1,201 @@
+use chrono::{DateTime, Duration, Utc};
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+
+use crate::history::SeriesCatalogue;
+use crate::models::{CompletionStatus, HistoryEntry, SeasonCompletion, SeriesCompletion, WatchStatus};
+
+/// Series with episodes left count as dropped once they have not been
+/// played for this long.
+pub const DROPPED_AFTER: Duration = Duration::days(60);
+
+/// Per series and season, how many of the available episodes have a
+/// completed play in `entries`, most recently watched series first. Only series
+/// with a catalogue are included.
+pub fn completion(entries: &[HistoryEntry], catalogues: &[SeriesCatalogue], now: DateTime<Utc>) -> Vec<SeriesCompletion> {
+    let mut watched_ids: HashMap<&str, HashSet<&str>> = HashMap::new();
+    let mut last_watched: HashMap<&str, DateTime<Utc>> = HashMap::new();
+    let mut titles: HashMap<&str, &str> = HashMap::new();
+    for entry in entries {
+        let Some(series_id) = entry.series_id.as_deref() else {
+            continue;
+        };
+        titles.entry(series_id).or_insert(&entry.title);
+        if let Some(played) = entry.played_at() {
+            let last = last_watched.entry(series_id).or_insert(played);
+            *last = (*last).max(played);
+        }
+        if let Some(content_id) = entry.content_id.as_deref().filter(|_| entry.watch_status == WatchStatus::Completed) {
+            watched_ids.entry(series_id).or_default().insert(content_id);
+        }
+    }
+
+    let no_ids = HashSet::new();
+    let mut series: Vec<SeriesCompletion> = catalogues
+        .iter()
+        .map(|catalogue| {
+            let series_id = catalogue.series_id.as_str();
+            let watched_ids = watched_ids.get(series_id).unwrap_or(&no_ids);
+
+            let mut seasons: Vec<SeasonCompletion> = catalogue
+                .seasons
+                .iter()
+                .map(|season| {
+                    let watched = season
+                        .episodes
+                        .iter()
+                        .filter(|ids| ids.iter().any(|id| watched_ids.contains(id.as_str())))
+                        .count();
+                    let available = season.episodes.len();
+                    SeasonCompletion {
+                        season_id: season.id.clone(),
+                        season_number: season.season_number,
+                        title: season.title.clone(),
+                        watched,
+                        available,
+                        completion: fraction(watched, available),
+                    }
+                })
+                .collect();
+            seasons.sort_by_key(|season| season.season_number);
+
+            let watched = seasons.iter().map(|season| season.watched).sum();
+            let available = seasons.iter().map(|season| season.available).sum();
+            let last_watched_at = last_watched.get(series_id).copied();
+            let status = if available > 0 && watched == available {
+                CompletionStatus::Finished
+            } else if last_watched_at.is_some_and(|last| now - last < DROPPED_AFTER) {
+                CompletionStatus::InProgress
+            } else {
+                CompletionStatus::Dropped
+            };
+
+            SeriesCompletion {
+                series_id: series_id.to_string(),
+                title: titles.get(series_id).copied().unwrap_or_default().to_string(),
+                watched,
+                available,
+                completion: fraction(watched, available),
+                status,
+                last_watched_at,
+                seasons,
+            }
+        })
+        .collect();
+    series.sort_by_key(|series| Reverse(series.last_watched_at));
+    series
+}
+
+fn fraction(watched: usize, available: usize) -> f64 {
+    if available > 0 {
+        watched as f64 / available as f64
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::{self, SeasonListing};
+
+    fn entry(content_id: &str, watched_at: &str, playhead: u32) -> HistoryEntry {
+        let mut entry = HistoryEntry {
+            content_id: Some(content_id.to_string()),
+            series_id: Some("frieren".to_string()),
+            playhead: Some(playhead),
+            duration_ms: Some(1_440_000),
+            ..HistoryEntry::test("item-0", Some(watched_at))
+        };
+        history::classify(&mut entry, history::DEFAULT_COMPLETION_THRESHOLD);
+        entry
+    }
+
+    fn season(id: &str, season_number: u32, episodes: &[&[&str]]) -> SeasonListing {
+        SeasonListing {
+            id: id.to_string(),
+            season_number,
+            title: format!("Season {}", season_number),
+            episodes: episodes
+                .iter()
+                .map(|ids| ids.iter().map(|id| id.to_string()).collect())
+                .collect(),
+        }
+    }
+
+    fn catalogue(seasons: Vec<SeasonListing>) -> SeriesCatalogue {
+        SeriesCatalogue {
+            series_id: "frieren".to_string(),
+            seasons,
+        }
+    }
+
+    fn at(raw: &str) -> DateTime<Utc> {
+        raw.parse().unwrap()
+    }
+
+    #[test]
+    fn counts_watched_episodes_per_season() {
+        let entries = vec![
+            entry("s2e1", "2024-05-09T20:00:00Z", 1440),
+            entry("s1e2", "2024-05-08T20:00:00Z", 1440),
+            entry("s1e1", "2024-05-07T20:00:00Z", 1440),
+        ];
+        let catalogues = vec![catalogue(vec![
+            season("season-2", 2, &[&["s2e1"], &["s2e2"]]),
+            season("season-1", 1, &[&["s1e1"], &["s1e2"]]),
+        ])];
+
+        let completion = completion(&entries, &catalogues, at("2024-05-10T00:00:00Z"));
+
+        assert_eq!(completion.len(), 1);
+        let series = &completion[0];
+        assert_eq!(series.title, "Frieren");
+        assert_eq!((series.watched, series.available), (3, 4));
+        assert_eq!(series.completion, 0.75);
+        assert_eq!(series.last_watched_at, Some(at("2024-05-09T20:00:00Z")));
+        let seasons: Vec<_> = series
+            .seasons
+            .iter()
+            .map(|season| (season.season_number, season.watched, season.available))
+            .collect();
+        assert_eq!(seasons, vec![(1, 2, 2), (2, 1, 2)]);
+    }
+
+    #[test]
+    fn dubbed_versions_and_rewatches_count_once() {
+        let entries = vec![
+            entry("s1e1-dub", "2024-05-09T20:00:00Z", 1440),
+            entry("s1e1", "2024-05-08T20:00:00Z", 1440),
+        ];
+        let catalogues = vec![catalogue(vec![season("season-1", 1, &[&["s1e1", "s1e1-dub"], &["s1e2"]])])];
+
+        let completion = completion(&entries, &catalogues, at("2024-05-10T00:00:00Z"));
+
+        assert_eq!(completion[0].watched, 1);
+    }
+
+    #[test]
+    fn barely_started_episodes_are_not_watched() {
+        let entries = vec![entry("s1e1", "2024-05-09T20:00:00Z", 120)];
+        let catalogues = vec![catalogue(vec![season("season-1", 1, &[&["s1e1"]])])];
+
+        let completion = completion(&entries, &catalogues, at("2024-05-10T00:00:00Z"));
+
+        assert_eq!(completion[0].watched, 0);
+        assert_eq!(completion[0].status, CompletionStatus::InProgress);
+    }
+
+    #[test]
+    fn status_follows_completion_and_recency() {
+        let entries = vec![entry("s1e1", "2024-01-01T20:00:00Z", 1440)];
+        let finished = vec![catalogue(vec![season("season-1", 1, &[&["s1e1"]])])];
+        let unfinished = vec![catalogue(vec![season("season-1", 1, &[&["s1e1"], &["s1e2"]])])];
+
+        let status = |catalogues: &[SeriesCatalogue], now: &str| completion(&entries, catalogues, at(now))[0].status;
+
+        assert_eq!(status(&finished, "2024-06-01T00:00:00Z"), CompletionStatus::Finished);
+        assert_eq!(status(&unfinished, "2024-01-15T00:00:00Z"), CompletionStatus::InProgress);
+        assert_eq!(status(&unfinished, "2024-06-01T00:00:00Z"), CompletionStatus::Dropped);
+    }
+}
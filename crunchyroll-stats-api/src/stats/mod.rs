@@ -0,0 +1,18 @@
+pub mod completion;
+pub mod rewatches;
+pub mod sessions;
+pub mod streaks;
+pub mod summary;
+
+pub use completion::completion;
+pub use rewatches::rewatches;
+pub use sessions::binges;
+pub use streaks::streaks;
+pub use summary::summarize;
+
+const MS_PER_HOUR: f64 = 60.0 * 60.0 * 1000.0;
+
+/// Hours rounded to two decimals, as shown in the web app.
+pub fn hours(ms: u64) -> f64 {
+    (ms as f64 / MS_PER_HOUR * 100.0).round() / 100.0
+}
@@ -0,0 +1,50 @@
+use super::hours;
+use crate::models::{HistoryEntry, RewatchStats};
+
+/// Splits watch time into first viewings and rewatches. Each entry is its
+/// content's latest play, which is a rewatch once an earlier play of it was
+/// finished.
+pub fn rewatches(entries: &[HistoryEntry]) -> RewatchStats {
+    let (rewatched, new): (Vec<&HistoryEntry>, Vec<&HistoryEntry>) =
+        entries.iter().partition(|entry| entry.rewatch_count > 0);
+    let new_ms = new.iter().map(|entry| entry.watched_ms).sum();
+    let rewatched_ms = rewatched.iter().map(|entry| entry.watched_ms).sum();
+
+    RewatchStats {
+        new_entries: new.len(),
+        new_ms,
+        new_hours: hours(new_ms),
+        rewatched_entries: rewatched.len(),
+        rewatched_ms,
+        rewatched_hours: hours(rewatched_ms),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history;
+
+    fn entry(playhead: u32, rewatch_count: u32) -> HistoryEntry {
+        let mut entry = HistoryEntry {
+            series_id: Some("series-1".to_string()),
+            playhead: Some(playhead),
+            duration_ms: Some(1_440_000),
+            rewatch_count,
+            ..HistoryEntry::test("item-0", None)
+        };
+        history::classify(&mut entry, history::DEFAULT_COMPLETION_THRESHOLD);
+        entry
+    }
+
+    #[test]
+    fn splits_watch_time_by_rewatch() {
+        let entries = vec![entry(1440, 0), entry(720, 0), entry(1440, 2)];
+
+        let stats = rewatches(&entries);
+
+        assert_eq!((stats.new_entries, stats.new_ms), (2, 2_160_000));
+        assert_eq!((stats.rewatched_entries, stats.rewatched_ms), (1, 1_440_000));
+        assert_eq!(stats.rewatched_hours, 0.4);
+    }
+}
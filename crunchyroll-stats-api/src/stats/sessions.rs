@@ -0,0 +1,157 @@
+use chrono::{DateTime, Duration, Utc};
+
+use super::hours;
+use crate::models::{HistoryEntry, ViewingSession};
+
+pub const DEFAULT_GAP: Duration = Duration::minutes(30);
+pub const DEFAULT_MIN_BINGE_EPISODES: usize = 3;
+
+/// Groups episodes into viewing sessions, oldest first. A session is a run
+/// of plays of the same series where each one starts within `gap` of the
+/// previous one ending; another series or a longer break starts a new one.
+/// Movies and entries without a play time are left out.
+pub fn viewing_sessions(entries: &[HistoryEntry], gap: Duration) -> Vec<ViewingSession> {
+    let mut plays: Vec<(DateTime<Utc>, &HistoryEntry, &str)> = entries
+        .iter()
+        .filter_map(|entry| {
+            let series_id = entry.series_id.as_deref()?;
+            Some((entry.played_at()?, entry, series_id))
+        })
+        .collect();
+    plays.sort_by_key(|(played, _, _)| *played);
+
+    let mut sessions: Vec<ViewingSession> = Vec::new();
+    for (played, entry, series_id) in plays {
+        let watched = entry.watched_ms;
+        let end = played + Duration::milliseconds(watched as i64);
+
+        if let Some(session) = sessions.last_mut() {
+            if session.series_id == series_id && played - session.end <= gap {
+                session.episodes += 1;
+                session.watched_ms += watched;
+                session.watched_hours = hours(session.watched_ms);
+                session.end = session.end.max(end);
+                continue;
+            }
+        }
+
+        sessions.push(ViewingSession {
+            series_id: series_id.to_string(),
+            title: entry.title.clone(),
+            episodes: 1,
+            watched_ms: watched,
+            watched_hours: hours(watched),
+            start: played,
+            end,
+        });
+    }
+    sessions
+}
+
+/// Sessions of at least `min_episodes`, most episodes first, then most
+/// watch time.
+pub fn binges(entries: &[HistoryEntry], gap: Duration, min_episodes: usize) -> Vec<ViewingSession> {
+    let mut binges: Vec<ViewingSession> = viewing_sessions(entries, gap)
+        .into_iter()
+        .filter(|session| session.episodes >= min_episodes)
+        .collect();
+    binges.sort_by(|a, b| {
+        b.episodes
+            .cmp(&a.episodes)
+            .then(b.watched_ms.cmp(&a.watched_ms))
+            .then(b.start.cmp(&a.start))
+    });
+    binges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history;
+    use crate::models::MediaKind;
+
+    fn entry(series_id: Option<&str>, watched_at: &str, playhead: u32) -> HistoryEntry {
+        let mut entry = HistoryEntry {
+            media_type: if series_id.is_some() { MediaKind::Episode } else { MediaKind::Movie },
+            series_id: series_id.map(str::to_string),
+            title: series_id.unwrap_or("Suzume").to_string(),
+            playhead: Some(playhead),
+            duration_ms: Some(1_440_000),
+            ..HistoryEntry::test("item-0", Some(watched_at))
+        };
+        history::classify(&mut entry, history::DEFAULT_COMPLETION_THRESHOLD);
+        entry
+    }
+
+    fn at(raw: &str) -> DateTime<Utc> {
+        raw.parse().unwrap()
+    }
+
+    #[test]
+    fn consecutive_episodes_form_one_session() {
+        // Newest first, like the API.
+        let entries = vec![
+            entry(Some("frieren"), "2024-05-09T21:00:00Z", 1440),
+            entry(Some("frieren"), "2024-05-09T20:30:00Z", 1440),
+            entry(Some("frieren"), "2024-05-09T20:00:00Z", 1440),
+        ];
+
+        let sessions = viewing_sessions(&entries, DEFAULT_GAP);
+
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].episodes, 3);
+        assert_eq!(sessions[0].watched_ms, 3 * 1_440_000);
+        assert_eq!(sessions[0].start, at("2024-05-09T20:00:00Z"));
+        assert_eq!(sessions[0].end, at("2024-05-09T21:24:00Z"));
+    }
+
+    #[test]
+    fn long_break_or_other_series_splits_sessions() {
+        let entries = vec![
+            entry(Some("frieren"), "2024-05-09T23:00:00Z", 1440),
+            entry(Some("spy-family"), "2024-05-09T20:30:00Z", 1440),
+            entry(Some("frieren"), "2024-05-09T20:00:00Z", 1440),
+        ];
+
+        let sessions = viewing_sessions(&entries, DEFAULT_GAP);
+
+        let series: Vec<_> = sessions.iter().map(|session| session.series_id.as_str()).collect();
+        assert_eq!(series, vec!["frieren", "spy-family", "frieren"]);
+    }
+
+    #[test]
+    fn gap_is_configurable() {
+        let entries = vec![
+            entry(Some("frieren"), "2024-05-09T22:00:00Z", 1440),
+            entry(Some("frieren"), "2024-05-09T20:00:00Z", 1440),
+        ];
+
+        assert_eq!(viewing_sessions(&entries, DEFAULT_GAP).len(), 2);
+        assert_eq!(viewing_sessions(&entries, Duration::hours(2)).len(), 1);
+    }
+
+    #[test]
+    fn movies_are_not_part_of_sessions() {
+        let entries = vec![entry(None, "2024-05-09T20:00:00Z", 1440)];
+        assert!(viewing_sessions(&entries, DEFAULT_GAP).is_empty());
+    }
+
+    #[test]
+    fn binges_are_filtered_and_ranked() {
+        let entries = vec![
+            entry(Some("spy-family"), "2024-05-10T21:00:00Z", 1440),
+            entry(Some("spy-family"), "2024-05-10T20:30:00Z", 1440),
+            entry(Some("spy-family"), "2024-05-10T20:00:00Z", 1440),
+            entry(Some("frieren"), "2024-05-09T21:30:00Z", 1440),
+            entry(Some("frieren"), "2024-05-09T21:00:00Z", 1440),
+            entry(Some("frieren"), "2024-05-09T20:30:00Z", 1440),
+            entry(Some("frieren"), "2024-05-09T20:00:00Z", 1440),
+            entry(Some("dandadan"), "2024-05-08T20:00:00Z", 1440),
+        ];
+
+        let binges = binges(&entries, DEFAULT_GAP, DEFAULT_MIN_BINGE_EPISODES);
+
+        let ranked: Vec<_> = binges.iter().map(|session| (session.series_id.as_str(), session.episodes)).collect();
+        assert_eq!(ranked, vec![("frieren", 4), ("spy-family", 3)]);
+    }
+}
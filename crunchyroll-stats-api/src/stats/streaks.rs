@@ -0,0 +1,168 @@
+use chrono::{Duration, NaiveDate};
+use chrono_tz::Tz;
+use std::collections::BTreeMap;
+
+use super::hours;
+use crate::models::{HistoryEntry, PeakDay, Streak, StreakStats};
+
+/// Streaks and peak day, with each play counted on the calendar day it
+/// fell on in `tz`. `today` is the current date in `tz`.
+pub fn streaks(entries: &[HistoryEntry], tz: Tz, today: NaiveDate) -> StreakStats {
+    let days = watch_time_by_day(entries, tz);
+
+    let mut longest = Streak::default();
+    let mut current = Streak::default();
+    for &day in days.keys() {
+        let continues = current.end.is_some_and(|end| end + Duration::days(1) == day);
+        current = if continues {
+            Streak {
+                days: current.days + 1,
+                end: Some(day),
+                ..current
+            }
+        } else {
+            Streak {
+                days: 1,
+                start: Some(day),
+                end: Some(day),
+            }
+        };
+        if current.days > longest.days {
+            longest = current.clone();
+        }
+    }
+
+    let current_streak = match current.end {
+        Some(end) if end == today || end + Duration::days(1) == today => current,
+        _ => Streak::default(),
+    };
+
+    // Ties go to the most recent day.
+    let peak_day = days
+        .iter()
+        .max_by_key(|(day, ms)| (**ms, **day))
+        .map(|(&date, &watched_ms)| PeakDay {
+            date,
+            watched_ms,
+            hours: hours(watched_ms),
+        });
+
+    StreakStats {
+        timezone: tz.name().to_string(),
+        longest_streak: longest,
+        current_streak,
+        peak_day,
+    }
+}
+
+fn watch_time_by_day(entries: &[HistoryEntry], tz: Tz) -> BTreeMap<NaiveDate, u64> {
+    let mut days = BTreeMap::new();
+    for entry in entries {
+        if let Some(played) = entry.played_at() {
+            let day = played.with_timezone(&tz).date_naive();
+            *days.entry(day).or_insert(0) += entry.watched_ms;
+        }
+    }
+    days
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history;
+
+    fn entry(watched_at: &str, playhead: u32) -> HistoryEntry {
+        let mut entry = HistoryEntry {
+            series_id: Some("series-1".to_string()),
+            playhead: Some(playhead),
+            duration_ms: Some(1_440_000),
+            ..HistoryEntry::test("item-0", Some(watched_at))
+        };
+        history::classify(&mut entry, history::DEFAULT_COMPLETION_THRESHOLD);
+        entry
+    }
+
+    fn date(raw: &str) -> NaiveDate {
+        raw.parse().unwrap()
+    }
+
+    #[test]
+    fn empty_history_has_no_streaks() {
+        let stats = streaks(&[], Tz::UTC, date("2024-05-10"));
+        assert_eq!(stats.longest_streak.days, 0);
+        assert_eq!(stats.current_streak.days, 0);
+        assert!(stats.peak_day.is_none());
+    }
+
+    #[test]
+    fn longest_and_current_streaks() {
+        let entries = vec![
+            entry("2024-05-09T20:00:00Z", 600),
+            entry("2024-05-08T20:00:00Z", 600),
+            entry("2024-05-05T20:00:00Z", 600),
+            entry("2024-05-04T20:00:00Z", 600),
+            entry("2024-05-03T20:00:00Z", 600),
+        ];
+
+        let stats = streaks(&entries, Tz::UTC, date("2024-05-10"));
+
+        assert_eq!(
+            stats.longest_streak,
+            Streak {
+                days: 3,
+                start: Some(date("2024-05-03")),
+                end: Some(date("2024-05-05")),
+            }
+        );
+        assert_eq!(stats.current_streak.days, 2);
+        assert_eq!(stats.current_streak.start, Some(date("2024-05-08")));
+    }
+
+    #[test]
+    fn current_streak_is_broken_by_a_missed_day() {
+        let entries = vec![entry("2024-05-08T20:00:00Z", 600)];
+
+        let stats = streaks(&entries, Tz::UTC, date("2024-05-10"));
+
+        assert_eq!(stats.longest_streak.days, 1);
+        assert_eq!(stats.current_streak, Streak::default());
+    }
+
+    #[test]
+    fn days_follow_the_requested_timezone() {
+        // 16:00 UTC on the 9th is already the 10th in Tokyo (UTC+9) while
+        // 06:00 UTC on the 10th is still the 9th in Los Angeles.
+        let entries = vec![
+            entry("2024-05-10T06:00:00Z", 600),
+            entry("2024-05-09T16:00:00Z", 600),
+        ];
+
+        let utc = streaks(&entries, Tz::UTC, date("2024-05-10"));
+        let tokyo = streaks(&entries, chrono_tz::Asia::Tokyo, date("2024-05-10"));
+        let los_angeles = streaks(&entries, chrono_tz::America::Los_Angeles, date("2024-05-09"));
+
+        assert_eq!(utc.longest_streak.days, 2);
+        assert_eq!(tokyo.longest_streak.days, 1);
+        assert_eq!(tokyo.peak_day.unwrap().date, date("2024-05-10"));
+        assert_eq!(los_angeles.longest_streak.days, 1);
+        assert_eq!(los_angeles.peak_day.unwrap().date, date("2024-05-09"));
+        assert_eq!(los_angeles.timezone, "America/Los_Angeles");
+    }
+
+    #[test]
+    fn peak_day_sums_watch_time_and_prefers_recent_ties() {
+        let entries = vec![
+            entry("2024-05-09T22:00:00Z", 1440),
+            entry("2024-05-09T21:00:00Z", 1440),
+            entry("2024-05-08T21:00:00Z", 1440),
+            entry("2024-05-07T21:00:00Z", 1440),
+            entry("2024-05-07T20:00:00Z", 1440),
+        ];
+
+        let peak = streaks(&entries, Tz::UTC, date("2024-05-10")).peak_day.unwrap();
+
+        assert_eq!(peak.date, date("2024-05-09"));
+        assert_eq!(peak.watched_ms, 2 * 1_440_000);
+        assert_eq!(peak.hours, 0.8);
+    }
+}
@@ -0,0 +1,155 @@
+use std::collections::HashSet;
+
+use super::hours;
+use crate::models::{HistoryEntry, MediaKind, StatsSummary, WatchStatus};
+
+/// Totals and watch time over `entries`. Counting follows the web app:
+/// episodes without a series id are grouped by their show title.
+pub fn summarize(entries: &[HistoryEntry]) -> StatsSummary {
+    let mut titles = HashSet::new();
+    let mut series = HashSet::new();
+    let mut movies = 0;
+    let mut episodes = 0;
+    let mut completed = 0;
+    let mut partial = 0;
+    let mut sampled = 0;
+    let mut total_watched_ms = 0;
+    let mut completion_sum = 0.0;
+    let mut completion_count = 0usize;
+
+    for entry in entries {
+        let title = entry.title.trim().to_lowercase();
+        if !title.is_empty() {
+            titles.insert(title.clone());
+        }
+
+        match entry.media_type {
+            MediaKind::Episode => {
+                episodes += 1;
+                let series_key = entry
+                    .series_id
+                    .as_deref()
+                    .map(|id| id.trim().to_lowercase())
+                    .unwrap_or(title);
+                if !series_key.is_empty() {
+                    series.insert(series_key);
+                }
+            }
+            MediaKind::Movie => movies += 1,
+        }
+
+        match entry.watch_status {
+            WatchStatus::Completed => completed += 1,
+            WatchStatus::Partial => partial += 1,
+            WatchStatus::Sampled => sampled += 1,
+        }
+
+        let watched = entry.watched_ms;
+        total_watched_ms += watched;
+        if let Some(duration_ms) = entry.duration_ms.filter(|duration_ms| *duration_ms > 0) {
+            if watched > 0 {
+                completion_sum += watched as f64 / duration_ms as f64;
+                completion_count += 1;
+            }
+        }
+    }
+
+    StatsSummary {
+        titles: titles.len(),
+        series: series.len(),
+        movies,
+        episodes,
+        completed,
+        partial,
+        sampled,
+        watched_ms: total_watched_ms,
+        watched_hours: hours(total_watched_ms),
+        average_completion: if completion_count > 0 {
+            completion_sum / completion_count as f64
+        } else {
+            0.0
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history;
+
+    fn entry(media_type: MediaKind, title: &str, series_id: Option<&str>, playhead: u32, duration_ms: u64) -> HistoryEntry {
+        let mut entry = HistoryEntry {
+            media_type,
+            series_id: series_id.map(str::to_string),
+            title: title.to_string(),
+            playhead: Some(playhead),
+            duration_ms: Some(duration_ms),
+            ..HistoryEntry::test("item-0", None)
+        };
+        history::classify(&mut entry, history::DEFAULT_COMPLETION_THRESHOLD);
+        entry
+    }
+
+    #[test]
+    fn empty_history_summarizes_to_zero() {
+        let summary = summarize(&[]);
+        assert_eq!(summary.titles, 0);
+        assert_eq!(summary.watched_ms, 0);
+        assert_eq!(summary.average_completion, 0.0);
+    }
+
+    #[test]
+    fn counts_titles_series_movies_and_episodes() {
+        let entries = vec![
+            entry(MediaKind::Episode, "Frieren", Some("series-1"), 1440, 1_440_000),
+            entry(MediaKind::Episode, "frieren ", Some("series-1"), 720, 1_440_000),
+            entry(MediaKind::Episode, "SPY x FAMILY", None, 0, 1_440_000),
+            entry(MediaKind::Movie, "Suzume", None, 6600, 6_600_000),
+        ];
+
+        let summary = summarize(&entries);
+
+        assert_eq!(summary.titles, 3);
+        assert_eq!(summary.series, 2);
+        assert_eq!(summary.episodes, 3);
+        assert_eq!(summary.movies, 1);
+    }
+
+    #[test]
+    fn watch_time_caps_playhead_at_duration() {
+        let entries = vec![
+            entry(MediaKind::Episode, "Frieren", Some("series-1"), 1800, 1_440_000),
+            entry(MediaKind::Movie, "Suzume", None, 3600, 7_200_000),
+        ];
+
+        let summary = summarize(&entries);
+
+        assert_eq!(summary.watched_ms, 1_440_000 + 3_600_000);
+        assert_eq!(summary.watched_hours, 1.4);
+        assert_eq!(summary.average_completion, 0.75);
+    }
+
+    #[test]
+    fn counts_entries_by_watch_status() {
+        let entries = vec![
+            entry(MediaKind::Episode, "Frieren", Some("series-1"), 1400, 1_440_000),
+            entry(MediaKind::Episode, "Frieren", Some("series-1"), 700, 1_440_000),
+            entry(MediaKind::Episode, "Frieren", Some("series-1"), 30, 1_440_000),
+            entry(MediaKind::Movie, "Suzume", None, 20, 6_600_000),
+        ];
+
+        let summary = summarize(&entries);
+
+        assert_eq!((summary.completed, summary.partial, summary.sampled), (1, 1, 2));
+    }
+
+    #[test]
+    fn unstarted_entries_do_not_lower_completion() {
+        let entries = vec![
+            entry(MediaKind::Episode, "Frieren", Some("series-1"), 1440, 1_440_000),
+            entry(MediaKind::Episode, "Frieren", Some("series-1"), 0, 1_440_000),
+        ];
+
+        assert_eq!(summarize(&entries).average_completion, 1.0);
+    }
+}
@@ -0,0 +1,463 @@
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex, Weak};
+use tokio::sync::{OwnedMutexGuard, RwLock};
+
+use crate::crypto::HistoryKey;
+use crate::models::{HistoryEntry, WatchStatus};
+
+/// Schema changes, applied in order. `PRAGMA user_version` records how many
+/// have run, so only append to this list.
+const MIGRATIONS: &[&str] = &[
+    "CREATE TABLE histories (
+        user_key TEXT PRIMARY KEY,
+        newest_played TEXT,
+        synced_since TEXT,
+        synced_at TEXT NOT NULL
+    );
+    CREATE TABLE history_entries (
+        user_key TEXT NOT NULL REFERENCES histories (user_key) ON DELETE CASCADE,
+        position INTEGER NOT NULL,
+        entry TEXT NOT NULL,
+        PRIMARY KEY (user_key, position)
+    );",
+    // History is now stored as one encrypted blob per user. Plaintext rows
+    // from version 1 are dropped; the next sync fetches them again.
+    "DROP TABLE history_entries;
+    DROP TABLE histories;
+    CREATE TABLE histories (
+        user_key TEXT PRIMARY KEY,
+        sealed BLOB NOT NULL
+    );",
+];
+
+/// Everything synced so far for one user.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StoredHistory {
+    /// Newest first, at most one entry per `content_id`.
+    pub entries: Vec<HistoryEntry>,
+    /// Newest `date_played` seen upstream. Syncs stop paginating here.
+    pub newest_played: Option<DateTime<Utc>>,
+    /// How far back `entries` is complete. `None` means the whole history.
+    pub synced_since: Option<DateTime<Utc>>,
+    /// When this was last brought up to date with Crunchyroll.
+    pub synced_at: DateTime<Utc>,
+    /// Plays that were superseded upstream by a later play of the same
+    /// content, summed up per `content_id` so they stay bounded by the
+    /// number of titles rather than growing with every play.
+    #[serde(default, deserialize_with = "prior_plays")]
+    pub prior_plays: HashMap<String, PriorPlays>,
+}
+
+/// Earlier plays of content whose entry now shows a later play.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PriorPlays {
+    /// How many of them were finished, each making the latest play a rewatch.
+    pub completed: u32,
+    pub first_played_at: DateTime<Utc>,
+}
+
+impl PriorPlays {
+    pub fn record(plays: &mut HashMap<String, PriorPlays>, content_id: String, played_at: DateTime<Utc>, status: WatchStatus) {
+        let prior = plays.entry(content_id).or_insert(PriorPlays {
+            completed: 0,
+            first_played_at: played_at,
+        });
+        if status == WatchStatus::Completed {
+            prior.completed += 1;
+        }
+        prior.first_played_at = prior.first_played_at.min(played_at);
+    }
+}
+
+/// A single superseded play, as histories stored before `PriorPlays` list
+/// them.
+#[derive(Deserialize)]
+struct LegacyPriorPlay {
+    content_id: String,
+    played_at: DateTime<Utc>,
+    watch_status: WatchStatus,
+}
+
+/// Reads `prior_plays` in either format, summing up a legacy list of plays.
+fn prior_plays<'de, D>(deserializer: D) -> std::result::Result<HashMap<String, PriorPlays>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Format {
+        Summed(HashMap<String, PriorPlays>),
+        Legacy(Vec<LegacyPriorPlay>),
+    }
+
+    Ok(match Format::deserialize(deserializer)? {
+        Format::Summed(plays) => plays,
+        Format::Legacy(legacy) => {
+            let mut plays = HashMap::new();
+            for play in legacy {
+                PriorPlays::record(&mut plays, play.content_id, play.played_at, play.watch_status);
+            }
+            plays
+        }
+    })
+}
+
+impl StoredHistory {
+    /// Whether the stored entries cover everything back to `cutoff`.
+    pub fn covers(&self, cutoff: Option<DateTime<Utc>>) -> bool {
+        match (self.synced_since, cutoff) {
+            (None, _) => true,
+            (Some(_), None) => false,
+            (Some(synced_since), Some(cutoff)) => cutoff >= synced_since,
+        }
+    }
+}
+
+/// Per-user synced watch history, keyed by `AppCache::cache_key`. Unlike
+/// `AppCache` entries never expire: they are what incremental syncs build on.
+/// Held in memory by default; the SQLite backend keeps it across restarts.
+///
+/// Both backends only hold `StoredHistory` sealed with the user's
+/// `HistoryKey`, as do the `AppCache` backends, so a copy of the database
+/// reveals no one's history. Plaintext only exists in memory while a request
+/// for it is being served.
+pub struct HistoryStore {
+    backend: Backend,
+    /// One lock per user while anyone holds or waits for it, see
+    /// `lock_user`.
+    users: Mutex<HashMap<String, Weak<tokio::sync::Mutex<()>>>>,
+}
+
+enum Backend {
+    Memory(RwLock<HashMap<String, Vec<u8>>>),
+    Sqlite(Arc<Mutex<Connection>>),
+}
+
+impl HistoryStore {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            backend: Backend::Memory(RwLock::new(HashMap::new())),
+            users: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Opens (or creates) the database at `path` and brings its schema up
+    /// to date.
+    pub fn sqlite(path: impl AsRef<Path>) -> Result<Arc<Self>> {
+        let path = path.as_ref();
+        let connection = Connection::open(path)
+            .with_context(|| format!("failed to open history database {}", path.display()))?;
+        Self::from_connection(connection)
+    }
+
+    /// Picks the backend from `HISTORY_STORE` (`memory` or `sqlite`) and,
+    /// for SQLite, the database file from `HISTORY_DB_PATH`.
+    pub fn from_env() -> Result<Arc<Self>> {
+        let kind = std::env::var("HISTORY_STORE").unwrap_or_else(|_| "memory".to_string());
+        match kind.as_str() {
+            "memory" => Ok(Self::new()),
+            "sqlite" => {
+                let path = std::env::var("HISTORY_DB_PATH")
+                    .unwrap_or_else(|_| "history.db".to_string());
+                Self::sqlite(path)
+            }
+            other => Err(anyhow!("unknown HISTORY_STORE {:?}, expected memory or sqlite", other)),
+        }
+    }
+
+    fn from_connection(mut connection: Connection) -> Result<Arc<Self>> {
+        migrate(&mut connection)?;
+        Ok(Arc::new(Self {
+            backend: Backend::Sqlite(Arc::new(Mutex::new(connection))),
+            users: Mutex::new(HashMap::new()),
+        }))
+    }
+
+    #[cfg(test)]
+    pub fn sqlite_in_memory() -> Arc<Self> {
+        Self::from_connection(Connection::open_in_memory().unwrap()).unwrap()
+    }
+
+    /// Waits until no one else holds the user's lock and takes it. The
+    /// history is one record per user, so anything that reads, changes and
+    /// writes it back holds this throughout, or a concurrent write for
+    /// another window would be lost.
+    pub async fn lock_user(&self, user_key: &str) -> OwnedMutexGuard<()> {
+        let lock = {
+            let mut users = self.users.lock().unwrap();
+            users.retain(|_, lock| lock.strong_count() > 0);
+            match users.get(user_key).and_then(Weak::upgrade) {
+                Some(lock) => lock,
+                None => {
+                    let lock = Arc::new(tokio::sync::Mutex::new(()));
+                    users.insert(user_key.to_string(), Arc::downgrade(&lock));
+                    lock
+                }
+            }
+        };
+        lock.lock_owned().await
+    }
+
+    /// The user's history, or `None` if nothing is stored or it was sealed
+    /// with another key, e.g. before a password change.
+    pub async fn get(&self, user_key: &str, key: &HistoryKey) -> Result<Option<StoredHistory>> {
+        let sealed = match &self.backend {
+            Backend::Memory(users) => users.read().await.get(user_key).cloned(),
+            Backend::Sqlite(connection) => {
+                let connection = connection.clone();
+                let user_key = user_key.to_string();
+                tokio::task::spawn_blocking(move || {
+                    read_sealed(&connection.lock().unwrap(), &user_key)
+                })
+                .await??
+            }
+        };
+        let Some(sealed) = sealed else {
+            return Ok(None);
+        };
+
+        match key.open(user_key, &sealed) {
+            Ok(plaintext) => Ok(Some(serde_json::from_slice(&plaintext)?)),
+            Err(error) => {
+                tracing::warn!(event = "history_unreadable", error = %error);
+                Ok(None)
+            }
+        }
+    }
+
+    pub async fn put(&self, user_key: String, key: &HistoryKey, history: StoredHistory) -> Result<()> {
+        let sealed = key.seal(&user_key, &serde_json::to_vec(&history)?)?;
+        match &self.backend {
+            Backend::Memory(users) => {
+                users.write().await.insert(user_key, sealed);
+                Ok(())
+            }
+            Backend::Sqlite(connection) => {
+                let connection = connection.clone();
+                tokio::task::spawn_blocking(move || {
+                    write_sealed(&connection.lock().unwrap(), &user_key, &sealed)
+                })
+                .await?
+            }
+        }
+    }
+}
+
+fn migrate(connection: &mut Connection) -> Result<()> {
+    let applied: usize = connection.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    for (version, migration) in MIGRATIONS.iter().enumerate().skip(applied) {
+        let tx = connection.transaction()?;
+        tx.execute_batch(migration)
+            .with_context(|| format!("history store migration {} failed", version + 1))?;
+        tx.pragma_update(None, "user_version", version + 1)?;
+        tx.commit()?;
+        tracing::info!(event = "history_store_migrated", version = version + 1);
+    }
+    Ok(())
+}
+
+fn read_sealed(connection: &Connection, user_key: &str) -> Result<Option<Vec<u8>>> {
+    Ok(connection
+        .query_row(
+            "SELECT sealed FROM histories WHERE user_key = ?1",
+            params![user_key],
+            |row| row.get(0),
+        )
+        .optional()?)
+}
+
+fn write_sealed(connection: &Connection, user_key: &str, sealed: &[u8]) -> Result<()> {
+    connection.execute(
+        "INSERT INTO histories (user_key, sealed) VALUES (?1, ?2)
+         ON CONFLICT (user_key) DO UPDATE SET sealed = excluded.sealed",
+        params![user_key, sealed],
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Genre;
+    use chrono::Duration;
+
+    fn make_entry(content_id: &str) -> HistoryEntry {
+        HistoryEntry {
+            content_id: Some(content_id.to_string()),
+            series_id: Some("series-1".to_string()),
+            episode_title: Some("Ep 1".to_string()),
+            playhead: Some(600),
+            duration_ms: Some(1_440_000),
+            watched_ms: 600_000,
+            watch_status: WatchStatus::Partial,
+            genres: vec![Genre::Fantasy],
+            ..HistoryEntry::test("item-0", Some(&Utc::now().to_rfc3339()))
+        }
+    }
+
+    fn stored(content_ids: &[&str]) -> StoredHistory {
+        StoredHistory {
+            entries: content_ids.iter().map(|id| make_entry(id)).collect(),
+            newest_played: Some(Utc::now()),
+            synced_since: Some(Utc::now() - Duration::days(365)),
+            synced_at: Utc::now(),
+            prior_plays: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn full_history_covers_any_cutoff() {
+        let stored = StoredHistory::default();
+        assert!(stored.covers(None));
+        assert!(stored.covers(Some(Utc::now())));
+    }
+
+    #[test]
+    fn partial_history_covers_only_later_cutoffs() {
+        let synced_since = Utc::now() - Duration::days(365);
+        let stored = StoredHistory {
+            synced_since: Some(synced_since),
+            ..Default::default()
+        };
+
+        assert!(stored.covers(Some(synced_since)));
+        assert!(stored.covers(Some(synced_since + Duration::days(1))));
+        assert!(!stored.covers(Some(synced_since - Duration::days(1))));
+        assert!(!stored.covers(None));
+    }
+
+    #[test]
+    fn legacy_prior_plays_are_summed_per_content() {
+        let json = serde_json::json!({
+            "entries": [],
+            "newest_played": null,
+            "synced_since": null,
+            "synced_at": "2024-03-10T00:00:00Z",
+            "prior_plays": [
+                {"content_id": "ep-1", "played_at": "2024-03-01T00:00:00Z", "watched_ms": 1_440_000, "watch_status": "completed"},
+                {"content_id": "ep-1", "played_at": "2024-02-01T00:00:00Z", "watched_ms": 60_000, "watch_status": "sampled"},
+                {"content_id": "ep-2", "played_at": "2024-03-02T00:00:00Z", "watched_ms": 1_440_000, "watch_status": "completed"},
+            ],
+        });
+
+        let stored: StoredHistory = serde_json::from_value(json).unwrap();
+
+        assert_eq!(stored.prior_plays.len(), 2);
+        assert_eq!(stored.prior_plays["ep-1"].completed, 1);
+        assert_eq!(stored.prior_plays["ep-1"].first_played_at.to_rfc3339(), "2024-02-01T00:00:00+00:00");
+        let reread: StoredHistory = serde_json::from_slice(&serde_json::to_vec(&stored).unwrap()).unwrap();
+        assert_eq!(reread.prior_plays, stored.prior_plays);
+    }
+
+    #[tokio::test]
+    async fn put_and_get_are_per_user() {
+        for store in [HistoryStore::new(), HistoryStore::sqlite_in_memory()] {
+            store.put("alice".to_string(), &HistoryKey::test(), stored(&["ep-1"])).await.unwrap();
+
+            assert!(store.get("alice", &HistoryKey::test()).await.unwrap().is_some());
+            assert!(store.get("bob", &HistoryKey::test()).await.unwrap().is_none());
+        }
+    }
+
+    #[tokio::test]
+    async fn sqlite_round_trips_history() {
+        let store = HistoryStore::sqlite_in_memory();
+        let history = stored(&["ep-2", "ep-1"]);
+        store.put("alice".to_string(), &HistoryKey::test(), history.clone()).await.unwrap();
+
+        let loaded = store.get("alice", &HistoryKey::test()).await.unwrap().unwrap();
+        assert_eq!(loaded.newest_played, history.newest_played);
+        assert_eq!(loaded.synced_since, history.synced_since);
+        assert_eq!(loaded.synced_at, history.synced_at);
+        let ids: Vec<_> = loaded.entries.iter().filter_map(|e| e.content_id.as_deref()).collect();
+        assert_eq!(ids, vec!["ep-2", "ep-1"]);
+        assert_eq!(loaded.entries[0].genres, vec![Genre::Fantasy]);
+    }
+
+    #[tokio::test]
+    async fn sqlite_put_replaces_previous_entries() {
+        let store = HistoryStore::sqlite_in_memory();
+        store.put("alice".to_string(), &HistoryKey::test(), stored(&["ep-1", "ep-2", "ep-3"])).await.unwrap();
+        store.put("alice".to_string(), &HistoryKey::test(), stored(&["ep-4"])).await.unwrap();
+
+        let loaded = store.get("alice", &HistoryKey::test()).await.unwrap().unwrap();
+        assert_eq!(loaded.entries.len(), 1);
+        assert_eq!(loaded.entries[0].content_id.as_deref(), Some("ep-4"));
+    }
+
+    #[tokio::test]
+    async fn sqlite_history_survives_reopen() {
+        let path = std::env::temp_dir().join(format!("history-{}.db", hex::encode(rand::random::<[u8; 8]>())));
+        {
+            let store = HistoryStore::sqlite(&path).unwrap();
+            store.put("alice".to_string(), &HistoryKey::test(), stored(&["ep-1"])).await.unwrap();
+        }
+
+        let reopened = HistoryStore::sqlite(&path).unwrap();
+        let loaded = reopened.get("alice", &HistoryKey::test()).await.unwrap();
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(loaded.map(|history| history.entries.len()), Some(1));
+    }
+
+    #[test]
+    fn migrations_run_once() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        migrate(&mut connection).unwrap();
+        migrate(&mut connection).unwrap();
+
+        let version: usize = connection
+            .pragma_query_value(None, "user_version", |row| row.get(0))
+            .unwrap();
+        assert_eq!(version, MIGRATIONS.len());
+    }
+
+    #[tokio::test]
+    async fn other_key_cannot_read_history() {
+        for store in [HistoryStore::new(), HistoryStore::sqlite_in_memory()] {
+            store.put("alice".to_string(), &HistoryKey::test(), stored(&["ep-1"])).await.unwrap();
+
+            let other = HistoryKey::from_bytes([8; 32]);
+            assert!(store.get("alice", &other).await.unwrap().is_none());
+        }
+    }
+
+    #[tokio::test]
+    async fn sqlite_file_holds_no_plaintext() {
+        let path = std::env::temp_dir().join(format!("history-{}.db", hex::encode(rand::random::<[u8; 8]>())));
+        let store = HistoryStore::sqlite(&path).unwrap();
+        store.put("alice".to_string(), &HistoryKey::test(), stored(&["ep-1"])).await.unwrap();
+        drop(store);
+
+        let raw = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+        for needle in [&b"Frieren"[..], b"series-1", b"fantasy"] {
+            assert!(!raw.windows(needle.len()).any(|window| window == needle));
+        }
+    }
+
+    #[test]
+    fn migration_drops_plaintext_rows() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        connection.execute_batch(MIGRATIONS[0]).unwrap();
+        connection.pragma_update(None, "user_version", 1).unwrap();
+        connection
+            .execute(
+                "INSERT INTO histories (user_key, synced_at) VALUES ('alice', '2024-01-01T00:00:00Z')",
+                [],
+            )
+            .unwrap();
+
+        migrate(&mut connection).unwrap();
+
+        let rows: i64 = connection
+            .query_row("SELECT COUNT(*) FROM histories", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(rows, 0);
+    }
+}
@@ -0,0 +1,445 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+
+use crate::crypto::HistoryKey;
+use crate::history::{self, History, HistoryWindow, Since};
+use crate::models::HistoryEntry;
+use crate::source::WatchDataSource;
+use crate::store::{HistoryStore, PriorPlays, StoredHistory};
+
+/// Brings the user's stored history up to date and returns the part of it
+/// inside `window`.
+///
+/// When the store already covers the window only entries newer than the
+/// newest known play are fetched, so pagination stops at the first page
+/// that reaches known data and genres are only resolved for new entries.
+/// Otherwise the window is fetched in full and replaces what was stored.
+/// Either way, stored entries that now show a later play are kept as prior
+/// plays, which is what rewatch detection builds on.
+///
+/// Syncs of the same user, for any window, run one at a time so none of
+/// them overwrites what another merged in.
+pub async fn sync_history<S: WatchDataSource>(
+    history: &History<'_, S>,
+    store: &HistoryStore,
+    user_key: &str,
+    key: &HistoryKey,
+    limit: Option<usize>,
+    window: &HistoryWindow,
+) -> Result<Vec<HistoryEntry>> {
+    let cutoff = window.cutoff();
+
+    let _syncing = store.lock_user(user_key).await;
+    let previous = store.get(user_key, key).await?;
+    let mut stored = match previous {
+        Some(mut stored) if stored.covers(cutoff) => {
+            let known = stored.newest_played.or(stored.synced_since);
+            let new_entries = history.fetch_newer_than(limit, known).await?;
+            tracing::info!(event = "history_sync_incremental", new_items = new_entries.len());
+            merge(&mut stored, new_entries);
+            stored.synced_at = Utc::now();
+            stored
+        }
+        previous => {
+            // Fetch up to now so later syncs can build on it; `until` is
+            // applied below.
+            let full_window = HistoryWindow {
+                since: cutoff.map_or(Since::All, Since::At),
+                until: None,
+            };
+            let entries = history.fetch_history(limit, &full_window).await?;
+            tracing::info!(event = "history_sync_full", items = entries.len());
+
+            let mut prior_plays = HashMap::new();
+            if let Some(previous) = previous {
+                prior_plays = previous.prior_plays;
+                supersede(&entries, previous.entries, &mut prior_plays);
+            }
+            StoredHistory {
+                newest_played: entries.iter().filter_map(HistoryEntry::played_at).max(),
+                synced_since: cutoff,
+                synced_at: Utc::now(),
+                entries,
+                prior_plays,
+            }
+        }
+    };
+    annotate(&mut stored);
+
+    let in_window = entries_in_window(&stored, window);
+    store.put(user_key.to_string(), key, stored).await?;
+    Ok(in_window)
+}
+
+/// The stored entries inside `window`, without contacting Crunchyroll,
+/// and how long ago they were synced. `None` if nothing covering the window
+/// was synced within `max_age`.
+pub async fn stored_history(
+    store: &HistoryStore,
+    user_key: &str,
+    key: &HistoryKey,
+    window: &HistoryWindow,
+    max_age: std::time::Duration,
+) -> Result<Option<(Vec<HistoryEntry>, std::time::Duration)>> {
+    let Some(stored) = store.get(user_key, key).await? else {
+        return Ok(None);
+    };
+    // A clock set back since the sync makes it look brand new.
+    let age = (Utc::now() - stored.synced_at).to_std().unwrap_or_default();
+    if age > max_age || !stored.covers(window.cutoff()) {
+        return Ok(None);
+    }
+    Ok(Some((entries_in_window(&stored, window), age)))
+}
+
+fn entries_in_window(stored: &StoredHistory, window: &HistoryWindow) -> Vec<HistoryEntry> {
+    let mut in_window: Vec<HistoryEntry> = stored
+        .entries
+        .iter()
+        .filter(|entry| entry.played_at().is_some_and(|played| window.contains(played)))
+        .cloned()
+        .collect();
+    backfill_ids(&mut in_window);
+    in_window
+}
+
+/// Puts `new_entries` in front of the stored ones. Crunchyroll lists each
+/// title once, at its latest play, so a stored entry for the same content is
+/// superseded by the new one.
+fn merge(stored: &mut StoredHistory, new_entries: Vec<HistoryEntry>) {
+    if new_entries.is_empty() {
+        return;
+    }
+
+    let old_entries = std::mem::take(&mut stored.entries);
+    let kept = supersede(&new_entries, old_entries, &mut stored.prior_plays);
+
+    stored.newest_played = new_entries
+        .iter()
+        .filter_map(HistoryEntry::played_at)
+        .chain(stored.newest_played)
+        .max();
+    stored.entries = new_entries.into_iter().chain(kept).collect();
+}
+
+/// Drops the `old_entries` whose content also appears in `entries`,
+/// recording them in `prior_plays` when `entries` shows a later play, and
+/// returns the rest.
+fn supersede(
+    entries: &[HistoryEntry],
+    old_entries: Vec<HistoryEntry>,
+    prior_plays: &mut HashMap<String, PriorPlays>,
+) -> Vec<HistoryEntry> {
+    let latest: HashMap<&str, Option<DateTime<Utc>>> = entries
+        .iter()
+        .filter_map(|entry| Some((entry.content_id.as_deref()?, entry.played_at())))
+        .collect();
+
+    let mut kept = Vec::new();
+    for entry in old_entries {
+        let Some(latest_played) = entry.content_id.as_deref().and_then(|id| latest.get(id)).copied() else {
+            kept.push(entry);
+            continue;
+        };
+        let played_at = entry.played_at();
+        if let (Some(content_id), Some(played_at), Some(latest_played)) = (entry.content_id, played_at, latest_played) {
+            if played_at < latest_played {
+                PriorPlays::record(prior_plays, content_id, played_at, entry.watch_status);
+            }
+        }
+    }
+    kept
+}
+
+/// Sets each entry's `rewatch_count` and `first_watched_at` from the prior
+/// plays of its content. Only finished prior plays make a rewatch; an
+/// unfinished one followed by a later play is the same viewing resumed.
+fn annotate(stored: &mut StoredHistory) {
+    for entry in &mut stored.entries {
+        let (rewatches, first) = match entry.content_id.as_deref().and_then(|id| stored.prior_plays.get(id)) {
+            Some(prior) => (prior.completed, Some(prior.first_played_at)),
+            None => (0, None),
+        };
+        entry.rewatch_count = rewatches;
+        entry.first_watched_at = first
+            .into_iter()
+            .chain(entry.played_at())
+            .min()
+            .map(|first| first.to_rfc3339());
+    }
+}
+
+/// Entries stored before ids were derived from the play carry positional
+/// `item-N` ids; give them their stable one.
+fn backfill_ids(entries: &mut [HistoryEntry]) {
+    for entry in entries {
+        if let (Some(content_id), Some(played)) = (entry.content_id.as_deref(), entry.played_at()) {
+            entry.id = history::stable_id(content_id, played);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::fixture::{self, FixtureSource};
+    use chrono::Duration;
+    use crunchyroll_rs::MediaCollection;
+
+    const USER_KEY: &str = "user-key";
+
+    fn played(id: &str, series_id: &str, date_played: DateTime<Utc>) -> crunchyroll_rs::list::WatchHistoryEntry {
+        played_to(id, series_id, date_played, 0)
+    }
+
+    fn played_to(
+        id: &str,
+        series_id: &str,
+        date_played: DateTime<Utc>,
+        playhead: u32,
+    ) -> crunchyroll_rs::list::WatchHistoryEntry {
+        fixture::played(
+            MediaCollection::Episode(fixture::episode(id, series_id, "Frieren", id)),
+            date_played,
+            playhead,
+        )
+    }
+
+    async fn sync(source: &FixtureSource, store: &HistoryStore, window: HistoryWindow) -> Vec<HistoryEntry> {
+        let session = "user@example.com".to_string();
+        let history = History::new(source, &session);
+        sync_history(&history, store, USER_KEY, &HistoryKey::test(), Some(100), &window)
+            .await
+            .unwrap()
+    }
+
+    fn content_ids(entries: &[HistoryEntry]) -> Vec<&str> {
+        entries.iter().filter_map(|entry| entry.content_id.as_deref()).collect()
+    }
+
+    #[tokio::test]
+    async fn resync_only_resolves_new_entries() {
+        let store = HistoryStore::new();
+        let now = Utc::now();
+        let first = FixtureSource::new()
+            .with_entry(played("ep-2", "series-1", now - Duration::hours(2)))
+            .with_entry(played("ep-1", "series-1", now - Duration::hours(3)));
+        let before = sync(&first, &store, HistoryWindow::default()).await;
+
+        let second = FixtureSource::new()
+            .with_entry(played("ep-9", "series-2", now - Duration::hours(1)))
+            .with_entry(played("ep-2", "series-1", now - Duration::hours(2)))
+            .with_entry(played("ep-1", "series-1", now - Duration::hours(3)));
+        let history = sync(&second, &store, HistoryWindow::default()).await;
+
+        assert_eq!(content_ids(&history), vec!["ep-9", "ep-2", "ep-1"]);
+        assert_eq!(history[1].id, before[0].id);
+        // Only series-2 was new; known entries were never re-resolved.
+        assert_eq!(second.series_lookups(), 1);
+    }
+
+    #[tokio::test]
+    async fn replayed_content_moves_to_the_front() {
+        let store = HistoryStore::new();
+        let now = Utc::now();
+        let first = FixtureSource::new()
+            .with_entry(played("ep-2", "series-1", now - Duration::hours(2)))
+            .with_entry(played("ep-1", "series-1", now - Duration::hours(3)));
+        sync(&first, &store, HistoryWindow::default()).await;
+
+        let second = FixtureSource::new()
+            .with_entry(played("ep-1", "series-1", now - Duration::hours(1)))
+            .with_entry(played("ep-2", "series-1", now - Duration::hours(2)));
+        let history = sync(&second, &store, HistoryWindow::default()).await;
+
+        assert_eq!(content_ids(&history), vec!["ep-1", "ep-2"]);
+    }
+
+    #[tokio::test]
+    async fn wider_window_than_stored_fetches_in_full() {
+        let store = HistoryStore::new();
+        let source = FixtureSource::new()
+            .with_entry(played("ep-2", "series-1", Utc::now() - Duration::days(1)))
+            .with_entry(played("ep-1", "series-2", Utc::now() - Duration::days(800)));
+
+        let recent = sync(&source, &store, HistoryWindow::default()).await;
+        let all = HistoryWindow {
+            since: Since::All,
+            until: None,
+        };
+        let everything = sync(&source, &store, all).await;
+        let recent_again = sync(&source, &store, HistoryWindow::default()).await;
+
+        assert_eq!(content_ids(&recent), vec!["ep-2"]);
+        assert_eq!(content_ids(&everything), vec!["ep-2", "ep-1"]);
+        assert_eq!(content_ids(&recent_again), vec!["ep-2"]);
+        assert!(store.get(USER_KEY, &HistoryKey::test()).await.unwrap().unwrap().synced_since.is_none());
+    }
+
+    #[tokio::test]
+    async fn until_is_applied_to_stored_entries() {
+        let store = HistoryStore::new();
+        let source = FixtureSource::new()
+            .with_entry(played("ep-2", "series-1", Utc::now() - Duration::days(1)))
+            .with_entry(played("ep-1", "series-1", Utc::now() - Duration::days(10)));
+
+        let history = sync(
+            &source,
+            &store,
+            HistoryWindow {
+                since: Since::Days(365),
+                until: Some(Utc::now() - Duration::days(5)),
+            },
+        )
+        .await;
+
+        assert_eq!(content_ids(&history), vec!["ep-1"]);
+        assert_eq!(store.get(USER_KEY, &HistoryKey::test()).await.unwrap().unwrap().entries.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn stored_history_is_served_while_fresh() {
+        let store = HistoryStore::new();
+        let source = FixtureSource::new()
+            .with_entry(played("ep-1", "series-1", Utc::now() - Duration::days(1)));
+        let window = HistoryWindow::default();
+        let max_age = std::time::Duration::from_secs(60);
+
+        assert!(stored_history(&store, USER_KEY, &HistoryKey::test(), &window, max_age).await.unwrap().is_none());
+        sync(&source, &store, window).await;
+
+        let served = stored_history(&store, USER_KEY, &HistoryKey::test(), &window, max_age).await.unwrap();
+        let (entries, age) = served.unwrap();
+        assert_eq!(entries.len(), 1);
+        assert!(age < max_age);
+        let all = HistoryWindow {
+            since: Since::All,
+            until: None,
+        };
+        assert!(stored_history(&store, USER_KEY, &HistoryKey::test(), &all, max_age).await.unwrap().is_none());
+        assert!(stored_history(&store, USER_KEY, &HistoryKey::test(), &window, std::time::Duration::ZERO)
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn replaying_finished_content_counts_as_rewatch() {
+        let store = HistoryStore::new();
+        let now = Utc::now();
+        let first_play = now - Duration::days(3);
+        let first = FixtureSource::new().with_entry(played_to("ep-1", "series-1", first_play, 1440));
+        let history = sync(&first, &store, HistoryWindow::default()).await;
+        assert_eq!(history[0].rewatch_count, 0);
+
+        let second = FixtureSource::new().with_entry(played_to("ep-1", "series-1", now - Duration::hours(1), 600));
+        let history = sync(&second, &store, HistoryWindow::default()).await;
+
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].rewatch_count, 1);
+        assert_eq!(history[0].first_watched_at, Some(first_play.to_rfc3339()));
+        let stored = store.get(USER_KEY, &HistoryKey::test()).await.unwrap().unwrap();
+        assert_eq!(stored.prior_plays.len(), 1);
+        assert_eq!(stored.prior_plays["ep-1"].completed, 1);
+    }
+
+    #[tokio::test]
+    async fn prior_plays_stay_one_per_content() {
+        let store = HistoryStore::new();
+        let now = Utc::now();
+        let first_play = now - Duration::days(10);
+        for days_ago in (1..=10).rev() {
+            let source = FixtureSource::new().with_entry(played_to("ep-1", "series-1", now - Duration::days(days_ago), 1440));
+            sync(&source, &store, HistoryWindow::default()).await;
+        }
+
+        let stored = store.get(USER_KEY, &HistoryKey::test()).await.unwrap().unwrap();
+        assert_eq!(stored.prior_plays.len(), 1);
+        assert_eq!(stored.prior_plays["ep-1"].completed, 9);
+        assert_eq!(stored.entries[0].rewatch_count, 9);
+        assert_eq!(stored.entries[0].first_watched_at, Some(first_play.to_rfc3339()));
+    }
+
+    #[tokio::test]
+    async fn resuming_an_unfinished_play_is_not_a_rewatch() {
+        let store = HistoryStore::new();
+        let now = Utc::now();
+        let started = now - Duration::days(1);
+        let first = FixtureSource::new().with_entry(played_to("ep-1", "series-1", started, 600));
+        sync(&first, &store, HistoryWindow::default()).await;
+
+        let second = FixtureSource::new().with_entry(played_to("ep-1", "series-1", now - Duration::hours(1), 1440));
+        let history = sync(&second, &store, HistoryWindow::default()).await;
+
+        assert_eq!(history[0].rewatch_count, 0);
+        assert_eq!(history[0].first_watched_at, Some(started.to_rfc3339()));
+    }
+
+    #[tokio::test]
+    async fn full_resync_keeps_prior_plays() {
+        let store = HistoryStore::new();
+        let now = Utc::now();
+        let first = FixtureSource::new().with_entry(played_to("ep-1", "series-1", now - Duration::days(3), 1440));
+        sync(&first, &store, HistoryWindow::default()).await;
+
+        let second = FixtureSource::new().with_entry(played_to("ep-1", "series-1", now - Duration::hours(1), 1440));
+        let all = HistoryWindow {
+            since: Since::All,
+            until: None,
+        };
+        let history = sync(&second, &store, all).await;
+
+        assert_eq!(history[0].rewatch_count, 1);
+        let history = sync(&second, &store, all).await;
+        assert_eq!(history[0].rewatch_count, 1);
+    }
+
+    #[tokio::test]
+    async fn concurrent_syncs_of_one_user_keep_each_others_entries() {
+        let store = HistoryStore::new();
+        let now = Utc::now();
+        let seed = FixtureSource::new().with_entry(played_to("ep-1", "series-1", now - Duration::days(3), 1440));
+        sync(&seed, &store, HistoryWindow::default()).await;
+
+        let source = FixtureSource::new()
+            .with_entry(played("ep-2", "series-2", now - Duration::hours(1)))
+            .with_entry(played_to("ep-1", "series-1", now - Duration::hours(2), 1440))
+            .with_entry(played("ep-0", "series-3", now - Duration::days(800)))
+            .with_latency("series-2", std::time::Duration::from_millis(50));
+        let all = HistoryWindow {
+            since: Since::All,
+            until: None,
+        };
+        // The incremental sync starts while the full one is still fetching
+        // and would otherwise finish last, writing back what it read before.
+        tokio::join!(sync(&source, &store, all), async {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            sync(&source, &store, HistoryWindow::default()).await
+        });
+
+        let stored = store.get(USER_KEY, &HistoryKey::test()).await.unwrap().unwrap();
+        assert_eq!(content_ids(&stored.entries), vec!["ep-2", "ep-1", "ep-0"]);
+        assert!(stored.synced_since.is_none());
+        assert_eq!(stored.prior_plays["ep-1"].completed, 1);
+    }
+
+    #[tokio::test]
+    async fn positional_ids_from_older_stores_are_replaced() {
+        let store = HistoryStore::new();
+        let played_at = Utc::now() - Duration::hours(1);
+        let source = FixtureSource::new().with_entry(played("ep-1", "series-1", played_at));
+        sync(&source, &store, HistoryWindow::default()).await;
+        let mut stored = store.get(USER_KEY, &HistoryKey::test()).await.unwrap().unwrap();
+        stored.entries[0].id = "item-0".to_string();
+        store.put(USER_KEY.to_string(), &HistoryKey::test(), stored).await.unwrap();
+
+        let max_age = std::time::Duration::from_secs(60);
+        let (served, _) = stored_history(&store, USER_KEY, &HistoryKey::test(), &HistoryWindow::default(), max_age)
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(served[0].id, history::stable_id("ep-1", played_at));
+    }
+}
@@ -8,6 +8,7 @@ vi.mock('@/lib/csrf', () => ({
 
 vi.mock('@/lib/crunchyroll/rust-api-client', () => ({
   validateCredentials: vi.fn(),
+  logout: vi.fn(),
   InvalidCredentialsError: class InvalidCredentialsError extends Error {
     constructor() {
       super('Invalid email or password');
@@ -46,7 +47,7 @@ function makeDeleteRequest(csrfToken?: string): NextRequest {
 beforeEach(() => {
   vi.clearAllMocks();
   // Default: credentials are valid
-  mockValidateCredentials.mockResolvedValue(undefined);
+  mockValidateCredentials.mockResolvedValue('rust-session-token');
 });
 
 // ---------------------------------------------------------------------------
@@ -208,11 +209,14 @@ describe('POST /api/auth — successful login', () => {
 
     const session = JSON.parse(cookie!.value) as {
       email: string;
-      password: string;
+      token: string;
+      password?: string;
       authenticated: boolean;
       expires_at: number;
     };
     expect(session.email).toBe('user@example.com');
+    expect(session.token).toBe('rust-session-token');
+    expect(session.password).toBeUndefined();
     expect(session.authenticated).toBe(true);
     expect(session.expires_at).toBeGreaterThan(Date.now());
   });
@@ -0,0 +1,66 @@
+import { describe, it, expect, vi, beforeEach } from 'vitest';
+import axios, { AxiosError, AxiosHeaders } from 'axios';
+import { NextRequest } from 'next/server';
+
+vi.mock('@/lib/session', async (importOriginal) => ({
+  ...(await importOriginal<typeof import('@/lib/session')>()),
+  validateSession: vi.fn(),
+}));
+
+import { GET } from '@/app/api/watch-history/route';
+import { validateSession } from '@/lib/session';
+
+const mockValidateSession = vi.mocked(validateSession);
+
+function makeGetRequest(): NextRequest {
+  return new NextRequest('http://localhost/api/watch-history');
+}
+
+function rustApiError(status: number, error: string): AxiosError {
+  const config = { headers: new AxiosHeaders() };
+  return new AxiosError('Request failed', 'ERR_BAD_REQUEST', config, null, {
+    status,
+    statusText: '',
+    data: { error },
+    headers: {},
+    config,
+  });
+}
+
+beforeEach(() => {
+  vi.restoreAllMocks();
+  mockValidateSession.mockResolvedValue({
+    email: 'user@example.com',
+    token: 'rust-session-token',
+    authenticated: true,
+    expires_at: Date.now() + 60 * 60 * 1000,
+  });
+});
+
+// ---------------------------------------------------------------------------
+// GET /api/watch-history — Rust API session
+// ---------------------------------------------------------------------------
+
+describe('GET /api/watch-history — Rust API session', () => {
+  it('returns 401 and clears cr_session when the Rust API no longer knows the token', async () => {
+    vi.spyOn(axios, 'get').mockRejectedValue(rustApiError(401, 'Invalid or expired token'));
+
+    const response = await GET(makeGetRequest());
+    const body = await response.json() as { error: string };
+
+    expect(response.status).toBe(401);
+    expect(body.error).toBe('Session expired');
+    const cookie = response.cookies.get('cr_session');
+    expect(cookie?.value).toBe('');
+    expect(cookie?.maxAge).toBe(0);
+  });
+
+  it('returns 500 and keeps cr_session when the Rust API fails otherwise', async () => {
+    vi.spyOn(axios, 'get').mockRejectedValue(rustApiError(502, 'Upstream unavailable'));
+
+    const response = await GET(makeGetRequest());
+
+    expect(response.status).toBe(500);
+    expect(response.cookies.get('cr_session')).toBeUndefined();
+  });
+});
@@ -1,7 +1,8 @@
 import { NextRequest, NextResponse } from 'next/server';
 import { z } from 'zod';
 import { validateCsrfToken } from '@/lib/csrf';
-import { validateCredentials, InvalidCredentialsError } from '@/lib/crunchyroll/rust-api-client';
+import { validateCredentials, logout, InvalidCredentialsError } from '@/lib/crunchyroll/rust-api-client';
+import { RUST_SESSION_MAX_AGE } from '@/lib/session';
 
 const loginSchema = z.object({
   email: z.string().min(1, 'Email is required').email('Invalid email address'),
@@ -30,9 +31,9 @@ export async function POST(request: NextRequest) {
 
     const { email, password, rememberMe } = result.data;
 
-    await validateCredentials(email, password);
+    const token = await validateCredentials(email, password);
 
-    const maxAge = rememberMe ? 60 * 60 * 24 * 30 : 60 * 60;
+    const maxAge = Math.min(rememberMe ? 60 * 60 * 24 * 30 : 60 * 60, RUST_SESSION_MAX_AGE);
     const expiresAt = Date.now() + maxAge * 1000;
 
     const response = NextResponse.json({
@@ -41,12 +42,12 @@ export async function POST(request: NextRequest) {
       email,
     });
 
-    // Store credentials in session for Rust API calls.
+    // Store the Rust API session token; the password is never persisted.
     response.cookies.set(
       'cr_session',
       JSON.stringify({
         email,
-        password,
+        token,
         authenticated: true,
         expires_at: expiresAt,
       }),
@@ -86,6 +87,18 @@ export async function DELETE(request: NextRequest) {
     );
   }
 
+  const sessionCookie = request.cookies.get('cr_session')?.value;
+  if (sessionCookie) {
+    try {
+      const { token } = JSON.parse(sessionCookie);
+      if (typeof token === 'string') {
+        await logout(token);
+      }
+    } catch {
+      // Malformed cookie; clearing it below is all that is left to do.
+    }
+  }
+
   const response = NextResponse.json({
     success: true,
     message: 'Logged out successfully',
@@ -30,7 +30,7 @@ export async function GET(request: NextRequest) {
 
     console.log('Fetching watch history via Rust API...');
 
-    const watchHistory = await getRustWatchHistory(session.email, session.password, isRefresh);
+    const watchHistory = await getRustWatchHistory(session.token, isRefresh);
 
     console.log(`Received ${watchHistory.length} items from Rust API`);
 
@@ -46,10 +46,20 @@ export async function GET(request: NextRequest) {
     return NextResponse.json(result);
   } catch (error) {
     if (error instanceof SessionError) {
-      return NextResponse.json(
+      const response = NextResponse.json(
         { error: error.message },
         { status: 401 }
       );
+      // Also covers a token the Rust API no longer knows, so the next
+      // request goes straight to the login page.
+      response.cookies.set('cr_session', '', {
+        httpOnly: true,
+        secure: true,
+        sameSite: 'lax',
+        path: '/',
+        maxAge: 0,
+      });
+      return response;
     }
 
     console.error('Watch history fetch error:', error);
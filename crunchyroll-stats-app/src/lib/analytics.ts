@@ -487,7 +487,8 @@ export function calculateAnalyticsSummary(entries: HistoryEntry[]): AnalyticsSum
 
     // New vs rewatched
     const cid = entry.contentId ?? `${entry.title}::${entry.episodeTitle ?? ''}`;
-    contentIdSeen.set(cid, (contentIdSeen.get(cid) ?? 0) + 1);
+    // The API remembers finished plays that Crunchyroll has since overwritten.
+    contentIdSeen.set(cid, (contentIdSeen.get(cid) ?? 0) + 1 + (entry.rewatchCount ?? 0));
 
     // Genre over time (past year)
     if (watchedMs >= oneYearAgo) {
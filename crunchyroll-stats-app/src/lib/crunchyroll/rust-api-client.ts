@@ -1,5 +1,6 @@
 import axios from 'axios';
 import { HistoryEntry } from '@/types/watch-history';
+import { SessionError } from '@/lib/session';
 
 const RUST_API_URL = process.env.RUST_API_URL || 'http://localhost:8080';
 
@@ -15,9 +16,14 @@ export class InvalidCredentialsError extends Error {
   }
 }
 
-export async function validateCredentials(email: string, password: string): Promise<void> {
+/**
+ * Logs in through the Rust API and returns its opaque session token.
+ * Credentials are only sent here; data calls use the token.
+ */
+export async function validateCredentials(email: string, password: string): Promise<string> {
   try {
-    await axios.post(`${RUST_API_URL}/api/auth`, { email, password });
+    const response = await axios.post(`${RUST_API_URL}/api/auth`, { email, password });
+    return response.data.token;
   } catch (error) {
     if (axios.isAxiosError(error)) {
       if (error.response?.status === 401) {
@@ -30,18 +36,30 @@ export async function validateCredentials(email: string, password: string): Prom
   }
 }
 
+/**
+ * Revokes the session on the Rust API. Best effort: a token that already
+ * expired is gone either way.
+ */
+export async function logout(token: string): Promise<void> {
+  try {
+    await axios.post(`${RUST_API_URL}/api/logout`, null, {
+      headers: { Authorization: `Bearer ${token}` },
+    });
+  } catch (error) {
+    console.error('Rust API logout failed:', error);
+  }
+}
+
 export async function getRustWatchHistory(
-  email: string,
-  password: string,
+  token: string,
   forceRefresh = false
 ): Promise<HistoryEntry[]> {
   try {
     console.log('Calling Rust API server...');
 
-    const response = await axios.post(`${RUST_API_URL}/api/watch-history`, {
-      email,
-      password,
-      force_refresh: forceRefresh || undefined,
+    const response = await axios.get(`${RUST_API_URL}/api/watch-history`, {
+      headers: { Authorization: `Bearer ${token}` },
+      params: { all: true, force_refresh: forceRefresh || undefined },
     });
 
     console.log(`Received ${response.data.data.length} items from Rust API`);
@@ -60,7 +78,10 @@ export async function getRustWatchHistory(
           title: item.title,
         episodeTitle: item.episode_title ?? undefined,
         watchedAt: item.watched_at ?? undefined,
-        progressMs: item.playhead != null ? item.playhead * 1000 : undefined,
+        progressMs: item.watched_ms ?? (item.playhead != null ? item.playhead * 1000 : undefined),
+        watchStatus: item.watch_status ?? undefined,
+        rewatchCount: item.rewatch_count ?? undefined,
+        firstWatchedAt: item.first_watched_at ?? undefined,
         durationMs: item.duration_ms ?? undefined,
         thumbnail,
         genres: Array.isArray(item.genres) ? item.genres : [],
@@ -69,6 +90,10 @@ export async function getRustWatchHistory(
 
     return watchHistory;
   } catch (error) {
+    if (axios.isAxiosError(error) && error.response?.status === 401) {
+      // The Rust API forgets sessions on restart and after a day idle.
+      throw new SessionError('Session expired');
+    }
     console.error('Rust API call failed:', error);
     if (axios.isAxiosError(error)) {
       const errorMsg = error.response?.data?.error || error.message;
@@ -1,8 +1,14 @@
 import { cookies } from 'next/headers';
 
+/**
+ * The longest the Rust API keeps a session (`MAX_AGE` in its session.rs).
+ * A cookie outliving it would only hold a dead token.
+ */
+export const RUST_SESSION_MAX_AGE = 60 * 60 * 24 * 30;
+
 interface SessionData {
   email: string;
-  password: string;
+  token: string;
   authenticated: boolean;
   expires_at: number;
 }
@@ -22,7 +28,7 @@ export async function validateSession(): Promise<SessionData> {
     throw new SessionError('Invalid session');
   }
 
-  if (!session.authenticated || !session.email || !session.password) {
+  if (!session.authenticated || !session.email || !session.token) {
     throw new SessionError('Invalid session');
   }
 
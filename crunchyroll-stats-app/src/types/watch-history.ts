@@ -8,6 +8,9 @@ export interface HistoryEntry {
   watchedAt?: string;
   progressMs?: number;
   durationMs?: number;
+  watchStatus?: 'completed' | 'partial' | 'sampled';
+  rewatchCount?: number;
+  firstWatchedAt?: string;
   thumbnail?: string;
   genres?: string[];
 }